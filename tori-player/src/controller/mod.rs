@@ -1,12 +1,346 @@
-use super::source;
-use crate::Result;
+use std::sync::Arc;
 
-#[derive(Debug, Default)]
-pub struct Controller {}
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use rand::seq::SliceRandom;
+
+use super::source::{self, Message, SeekTarget};
+use super::{
+    gain::ReplayGainMode,
+    output::{self, OutputKind},
+    tap::SampleTap,
+};
+use crate::{Error, Result};
+
+pub mod playlist;
+use playlist::Track;
+
+mod shared_state;
+pub(crate) use shared_state::SharedState;
+pub use shared_state::{PlayerStatus, MAX_VOLUME};
+
+/// Controls the player thread. Keeps the playlist and the playback state (pause, volume, etc.)
+/// that are shared with the player thread.
+#[derive(Debug)]
+pub struct Controller {
+    state: Arc<SharedState>,
+    sender: Sender<Message>,
+    errors: Receiver<Error>,
+}
+
+impl Default for Controller {
+    fn default() -> Self {
+        let state = Arc::new(SharedState::default());
+        let (sender, receiver) = unbounded();
+        let (error_sender, errors) = unbounded();
+        source::start_player_thread(state.clone(), receiver, error_sender);
+        Self {
+            state,
+            sender,
+            errors,
+        }
+    }
+}
 
 impl Controller {
+    /// Replaces the playlist with `path` and starts playing it
     pub fn play(&mut self, path: &str) -> Result<()> {
-        source::start_player_thread(path);
+        {
+            let mut playlist = self.state.playlist();
+            playlist.tracks = vec![Track::new(path)];
+            playlist.position = Some(0);
+        }
+        self.send(Message::Load)
+    }
+
+    /// Appends `path` to the playlist. Starts playing it if nothing is playing.
+    pub fn queue(&mut self, path: &str) -> Result<()> {
+        let idle = {
+            let mut playlist = self.state.playlist();
+            playlist.tracks.push(Track::new(path));
+            if playlist.position.is_none() {
+                playlist.position = Some(playlist.tracks.len() - 1);
+                true
+            } else {
+                false
+            }
+        };
+
+        if idle {
+            self.send(Message::Load)?;
+        }
         Ok(())
     }
+
+    /// Inserts `path` right after the current track. Starts playing it if nothing is playing.
+    pub fn play_next(&mut self, path: &str) -> Result<()> {
+        {
+            let mut playlist = self.state.playlist();
+            if let Some(i) = playlist.position {
+                playlist.tracks.insert(i + 1, Track::new(path));
+                return Ok(());
+            }
+        }
+        self.queue(path)
+    }
+
+    /// Removes the track at `index`. If it's the current one, the next track starts playing.
+    pub fn playlist_remove(&mut self, index: usize) -> Result<()> {
+        let was_current = {
+            let mut playlist = self.state.playlist();
+            if index >= playlist.tracks.len() {
+                return Err(format!("Playlist has no track at position {}", index).into());
+            }
+            let was_current = playlist.position == Some(index);
+            playlist.remove(index);
+            was_current
+        };
+
+        if was_current {
+            self.send(Message::Load)?;
+        }
+        Ok(())
+    }
+
+    /// Moves the track at `from` so it ends up at index `to`
+    pub fn playlist_move(&mut self, from: usize, to: usize) -> Result<()> {
+        let mut playlist = self.state.playlist();
+        let len = playlist.tracks.len();
+        if from >= len || to >= len {
+            return Err(format!("Can't move track {} to {}, there are {}", from, to, len).into());
+        }
+        playlist.move_track(from, to);
+        Ok(())
+    }
+
+    /// Removes every track except the current one, like mpv's `playlist-clear`
+    pub fn playlist_clear(&mut self) -> Result<()> {
+        let mut playlist = self.state.playlist();
+        let current = playlist.position.map(|i| playlist.tracks.swap_remove(i));
+        playlist.tracks = current.into_iter().collect();
+        playlist.position = (!playlist.tracks.is_empty()).then_some(0);
+        Ok(())
+    }
+
+    /// Starts playing the track at `index`
+    pub fn playlist_play_index(&mut self, index: usize) -> Result<()> {
+        {
+            let mut playlist = self.state.playlist();
+            if index >= playlist.tracks.len() {
+                return Err(format!("Playlist has no track at position {}", index).into());
+            }
+            playlist.position = Some(index);
+        }
+        self.send(Message::Load)
+    }
+
+    pub fn playlist_next(&mut self) -> Result<()> {
+        {
+            let mut playlist = self.state.playlist();
+            match playlist.next_position() {
+                Some(i) => playlist.position = Some(i),
+                None => return Err("No next song".into()),
+            }
+        }
+        self.send(Message::Load)
+    }
+
+    pub fn playlist_previous(&mut self) -> Result<()> {
+        {
+            let mut playlist = self.state.playlist();
+            match playlist.previous_position() {
+                Some(i) => playlist.position = Some(i),
+                None => return Err("No previous song".into()),
+            }
+        }
+        self.send(Message::Load)
+    }
+
+    /// Seeks forward (or backward, if negative) by some amount of seconds
+    pub fn seek(&mut self, seconds: f64) -> Result<()> {
+        self.send(Message::Seek(SeekTarget::Relative(seconds)))
+    }
+
+    /// Seeks to a percentage of the current track
+    pub fn seek_absolute(&mut self, percent: f64) -> Result<()> {
+        self.send(Message::Seek(SeekTarget::Percent(percent)))
+    }
+
+    /// Shuffles the playlist, keeping the current track playing
+    pub fn shuffle(&mut self) -> Result<()> {
+        let mut playlist = self.state.playlist();
+        let current = playlist.position.map(|i| playlist.tracks.remove(i));
+        playlist.tracks.shuffle(&mut rand::thread_rng());
+        if let Some(current) = current {
+            playlist.tracks.insert(0, current);
+            playlist.position = Some(0);
+        }
+        Ok(())
+    }
+
+    pub fn toggle_pause(&mut self) -> Result<()> {
+        let paused = !self.state.paused();
+        self.state.set_paused(paused);
+        self.send(if paused {
+            Message::Pause
+        } else {
+            Message::Resume
+        })
+    }
+
+    /// Whether the player is paused, which is what [toggle_pause](Self::toggle_pause) toggles. It
+    /// can be paused while nothing is playing, so the next song starts paused. While paused, the
+    /// player thread stops decoding and pauses the output stream.
+    pub fn paused(&self) -> bool {
+        self.state.paused()
+    }
+
+    pub fn status(&self) -> PlayerStatus {
+        self.state.status()
+    }
+
+    pub fn toggle_loop_file(&mut self) {
+        self.state.set_loop_file(!self.state.loop_file());
+    }
+
+    /// Makes the current track play again when it ends, like mpv's `loop-file`
+    pub fn set_loop_file(&mut self, loop_file: bool) {
+        self.state.set_loop_file(loop_file);
+    }
+
+    pub fn looping_file(&self) -> bool {
+        self.state.loop_file()
+    }
+
+    /// Makes the playlist start again from the first track after the last one ends
+    pub fn set_loop_playlist(&mut self, loop_playlist: bool) {
+        self.state.playlist().loop_playlist = loop_playlist;
+    }
+
+    pub fn looping_playlist(&self) -> bool {
+        self.state.playlist().loop_playlist
+    }
+
+    pub fn volume(&self) -> i64 {
+        self.state.volume()
+    }
+
+    pub fn set_volume(&mut self, volume: i64) {
+        self.state.set_volume(volume);
+    }
+
+    pub fn toggle_mute(&mut self) {
+        self.state.set_muted(!self.state.muted());
+    }
+
+    pub fn muted(&self) -> bool {
+        self.state.muted()
+    }
+
+    /// Sets which ReplayGain values are applied. Takes effect right away, including on the
+    /// current track.
+    pub fn set_replay_gain_mode(&mut self, mode: ReplayGainMode) {
+        self.state.set_replay_gain_mode(mode);
+    }
+
+    pub fn replay_gain_mode(&self) -> ReplayGainMode {
+        self.state.replay_gain_mode()
+    }
+
+    pub fn media_title(&self) -> String {
+        self.state.media_title()
+    }
+
+    /// Position in the current track, in seconds
+    pub fn time_pos(&self) -> f64 {
+        self.state.time_pos()
+    }
+
+    /// Duration of the current track, in seconds. Zero if unknown.
+    pub fn duration(&self) -> f64 {
+        self.state.duration()
+    }
+
+    /// Time left in the current track, in seconds. `None` if the duration is unknown, like in
+    /// live streams.
+    pub fn time_remaining(&self) -> Option<f64> {
+        let duration = self.state.duration();
+        (duration > 0.0).then(|| (duration - self.state.time_pos()).max(0.0))
+    }
+
+    pub fn playlist_count(&self) -> usize {
+        self.state.playlist().tracks.len()
+    }
+
+    pub fn playlist_track_title(&self, i: usize) -> Result<String> {
+        self.state
+            .playlist()
+            .tracks
+            .get(i)
+            .map(Track::display_title)
+            .ok_or_else(|| format!("Playlist has no track at position {}", i).into())
+    }
+
+    pub fn playlist_track_path(&self, i: usize) -> Result<String> {
+        self.state
+            .playlist()
+            .tracks
+            .get(i)
+            .map(|track| track.path.clone())
+            .ok_or_else(|| format!("Playlist has no track at position {}", i).into())
+    }
+
+    pub fn playlist_position(&self) -> Option<usize> {
+        self.state.playlist().position
+    }
+
+    /// Selects where the audio goes. The current output is closed and the new one is opened right
+    /// away, without restarting the track.
+    pub fn set_output(&mut self, kind: OutputKind) -> Result<()> {
+        self.state.set_output_kind(kind);
+        self.send(Message::ReopenOutput)
+    }
+
+    /// Sets the cpal host the output is opened on, like "alsa" or "jack". `None` is the default
+    /// host. Takes effect the next time the output is opened.
+    pub fn set_output_host(&mut self, host: Option<String>) {
+        self.state.set_output_host(host);
+    }
+
+    /// Names of the output devices of the current host
+    pub fn output_devices(&self) -> Result<Vec<String>> {
+        let devices =
+            output::output_devices(self.state.output_host().as_deref()).map_err(Error::from)?;
+        Ok(devices)
+    }
+
+    /// Moves playback to another output device, without restarting the track. `None` is the
+    /// host's default device.
+    pub fn set_output_device(&mut self, device: Option<String>) -> Result<()> {
+        self.state.set_output_device(device);
+        self.send(Message::ReopenOutput)
+    }
+
+    /// Starts copying the samples that are being played, so they can be read from the returned
+    /// tap. The copying stops when the tap is dropped.
+    pub fn sample_tap(&self) -> SampleTap {
+        SampleTap::new(self.state.tap().clone())
+    }
+
+    /// Returns the next error reported by the player thread, if any. Tracks that fail to play are
+    /// skipped, so these should be polled regularly to let the user know.
+    pub fn take_error(&mut self) -> Option<Error> {
+        self.errors.try_recv().ok()
+    }
+
+    fn send(&self, message: Message) -> Result<()> {
+        self.sender
+            .send(message)
+            .map_err(|_| "The player thread has stopped".into())
+    }
+}
+
+impl Drop for Controller {
+    fn drop(&mut self) {
+        self.sender.send(Message::Quit).ok();
+    }
 }
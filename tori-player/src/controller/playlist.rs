@@ -0,0 +1,112 @@
+/// A single entry of the [Playlist]
+#[derive(Debug, Clone, Default)]
+pub struct Track {
+    pub path: String,
+    /// Only known after the track has been probed by the player thread
+    pub title: Option<String>,
+}
+
+impl Track {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.into(),
+            title: None,
+        }
+    }
+
+    /// Title of the track, or the last part of its path if we don't know the title yet. The same
+    /// as mpv's `playlist/N/title` with a fallback to `playlist/N/filename`.
+    pub fn display_title(&self) -> String {
+        self.title.clone().unwrap_or_else(|| filename(&self.path))
+    }
+}
+
+/// Extracts the last part (separated by '/') of a path
+pub fn filename(path: &str) -> String {
+    path.trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or(path)
+        .to_string()
+}
+
+/// The player's internal queue of tracks
+#[derive(Debug, Default)]
+pub struct Playlist {
+    pub tracks: Vec<Track>,
+    /// Index of the track that's currently playing, if any
+    pub position: Option<usize>,
+    /// Whether the first track comes after the last one, like mpv's `loop-playlist`
+    pub loop_playlist: bool,
+}
+
+impl Playlist {
+    pub fn current(&self) -> Option<&Track> {
+        self.position.and_then(|i| self.tracks.get(i))
+    }
+
+    pub fn current_mut(&mut self) -> Option<&mut Track> {
+        self.position.and_then(|i| self.tracks.get_mut(i))
+    }
+
+    /// The track that comes after the current one, if any
+    pub fn peek_next(&self) -> Option<&Track> {
+        self.next_position().and_then(|i| self.tracks.get(i))
+    }
+
+    /// Index of the track that comes after the current one, if any
+    pub fn next_position(&self) -> Option<usize> {
+        match self.position {
+            Some(i) if i + 1 < self.tracks.len() => Some(i + 1),
+            Some(_) if self.loop_playlist => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Index of the track that comes before the current one, if any
+    pub fn previous_position(&self) -> Option<usize> {
+        match self.position {
+            Some(i) if i >= 1 => Some(i - 1),
+            Some(_) if self.loop_playlist => Some(self.tracks.len() - 1),
+            _ => None,
+        }
+    }
+
+    /// Removes the track at `index`, keeping the position on the same track. If it was the current
+    /// one, the position stays the same, so the track after it becomes the current one.
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> Track {
+        let track = self.tracks.remove(index);
+        self.position = match self.position {
+            Some(i) if i > index => Some(i - 1),
+            Some(i) if i == index && i >= self.tracks.len() => None,
+            other => other,
+        };
+        track
+    }
+
+    /// Moves the track at `from` so it ends up at index `to`, keeping the position on the same
+    /// track. Panics if either index is out of bounds.
+    pub fn move_track(&mut self, from: usize, to: usize) {
+        let track = self.tracks.remove(from);
+        self.tracks.insert(to, track);
+        self.position = self.position.map(|i| {
+            if i == from {
+                to
+            } else if from < i && i <= to {
+                i - 1
+            } else if to <= i && i < from {
+                i + 1
+            } else {
+                i
+            }
+        });
+    }
+
+    /// Moves to the next track. Returns `false` if there's no next track, in which case the
+    /// playlist stops playing.
+    pub fn advance(&mut self) -> bool {
+        self.position = self.next_position();
+        self.position.is_some()
+    }
+}
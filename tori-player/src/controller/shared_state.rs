@@ -0,0 +1,214 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+    Arc, Mutex, MutexGuard,
+};
+
+use super::playlist::Playlist;
+use crate::gain::{ReplayGain, ReplayGainMode};
+use crate::output::OutputKind;
+use crate::tap::TapBuffer;
+
+/// Maximum volume, in percent. The same as mpv's default `volume-max`.
+pub const MAX_VOLUME: i64 = 130;
+
+/// What the player thread is doing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PlayerStatus {
+    /// Nothing was played yet
+    #[default]
+    Idle,
+    /// Opening a track
+    Loading,
+    Playing,
+    /// Neither decoding nor playing, until it's resumed
+    Paused,
+    /// The end of the playlist was reached
+    Stopped,
+    /// The last track failed to play, and there was nothing else to play
+    Errored,
+}
+
+/// State shared between the [Controller](super::Controller), the player thread and the audio
+/// output callback.
+#[derive(Debug)]
+pub struct SharedState {
+    playlist: Mutex<Playlist>,
+    media_title: Mutex<String>,
+    status: Mutex<PlayerStatus>,
+    /// Whether the user wants playback to be paused. The player thread pauses when it sees this,
+    /// and then sets the status to [PlayerStatus::Paused].
+    paused: AtomicBool,
+    muted: AtomicBool,
+    loop_file: AtomicBool,
+    volume: AtomicI64,
+    /// Position of the last decoded packet, in milliseconds
+    time_pos: AtomicU64,
+    /// Duration of the current track, in milliseconds. Zero if unknown.
+    duration: AtomicU64,
+    output_kind: Mutex<OutputKind>,
+    /// cpal host the output is opened on. `None` is the default host.
+    output_host: Mutex<Option<String>>,
+    /// Name of the device the output is opened on. `None` is the host's default device.
+    output_device: Mutex<Option<String>>,
+    replay_gain_mode: Mutex<ReplayGainMode>,
+    /// ReplayGain values of the track that's being decoded
+    replay_gain: Mutex<ReplayGain>,
+    /// Copy of the samples that are being played, for visualizers
+    tap: Arc<TapBuffer>,
+}
+
+impl Default for SharedState {
+    fn default() -> Self {
+        Self {
+            playlist: Mutex::default(),
+            media_title: Mutex::default(),
+            status: Mutex::default(),
+            paused: AtomicBool::new(false),
+            muted: AtomicBool::new(false),
+            loop_file: AtomicBool::new(false),
+            volume: AtomicI64::new(100),
+            time_pos: AtomicU64::new(0),
+            duration: AtomicU64::new(0),
+            output_kind: Mutex::default(),
+            output_host: Mutex::default(),
+            output_device: Mutex::default(),
+            replay_gain_mode: Mutex::default(),
+            replay_gain: Mutex::default(),
+            tap: Arc::default(),
+        }
+    }
+}
+
+impl SharedState {
+    pub fn playlist(&self) -> MutexGuard<'_, Playlist> {
+        self.playlist.lock().unwrap()
+    }
+
+    pub fn media_title(&self) -> String {
+        self.media_title.lock().unwrap().clone()
+    }
+
+    pub fn set_media_title(&self, title: String) {
+        *self.media_title.lock().unwrap() = title;
+    }
+
+    pub fn status(&self) -> PlayerStatus {
+        *self.status.lock().unwrap()
+    }
+
+    pub fn set_status(&self, status: PlayerStatus) {
+        *self.status.lock().unwrap() = status;
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn muted(&self) -> bool {
+        self.muted.load(Ordering::Relaxed)
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+
+    pub fn loop_file(&self) -> bool {
+        self.loop_file.load(Ordering::Relaxed)
+    }
+
+    pub fn set_loop_file(&self, loop_file: bool) {
+        self.loop_file.store(loop_file, Ordering::Relaxed);
+    }
+
+    pub fn volume(&self) -> i64 {
+        self.volume.load(Ordering::Relaxed)
+    }
+
+    pub fn set_volume(&self, volume: i64) {
+        self.volume
+            .store(volume.clamp(0, MAX_VOLUME), Ordering::Relaxed);
+    }
+
+    /// Linear gain that should be applied to the output samples, including ReplayGain
+    pub fn gain(&self) -> f32 {
+        if self.muted() {
+            0.0
+        } else {
+            let replay_gain = self.replay_gain().factor(self.replay_gain_mode());
+            self.volume() as f32 / 100.0 * replay_gain
+        }
+    }
+
+    pub fn replay_gain_mode(&self) -> ReplayGainMode {
+        *self.replay_gain_mode.lock().unwrap()
+    }
+
+    pub fn set_replay_gain_mode(&self, mode: ReplayGainMode) {
+        *self.replay_gain_mode.lock().unwrap() = mode;
+    }
+
+    pub fn replay_gain(&self) -> ReplayGain {
+        *self.replay_gain.lock().unwrap()
+    }
+
+    pub fn set_replay_gain(&self, replay_gain: ReplayGain) {
+        *self.replay_gain.lock().unwrap() = replay_gain;
+    }
+
+    pub fn time_pos(&self) -> f64 {
+        self.time_pos.load(Ordering::Relaxed) as f64 / 1000.0
+    }
+
+    pub fn set_time_pos(&self, seconds: f64) {
+        self.time_pos
+            .store((seconds * 1000.0) as u64, Ordering::Relaxed);
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.duration.load(Ordering::Relaxed) as f64 / 1000.0
+    }
+
+    pub fn set_duration(&self, seconds: f64) {
+        self.duration
+            .store((seconds * 1000.0) as u64, Ordering::Relaxed);
+    }
+
+    pub fn output_kind(&self) -> OutputKind {
+        self.output_kind.lock().unwrap().clone()
+    }
+
+    pub fn set_output_kind(&self, kind: OutputKind) {
+        *self.output_kind.lock().unwrap() = kind;
+    }
+
+    pub fn output_host(&self) -> Option<String> {
+        self.output_host.lock().unwrap().clone()
+    }
+
+    pub fn set_output_host(&self, host: Option<String>) {
+        *self.output_host.lock().unwrap() = host;
+    }
+
+    pub fn output_device(&self) -> Option<String> {
+        self.output_device.lock().unwrap().clone()
+    }
+
+    pub fn set_output_device(&self, device: Option<String>) {
+        *self.output_device.lock().unwrap() = device;
+    }
+
+    pub fn tap(&self) -> &Arc<TapBuffer> {
+        &self.tap
+    }
+
+    /// Resets the state of the current track, for when nothing is playing
+    pub fn clear_track(&self) {
+        self.set_media_title(String::new());
+        self.set_time_pos(0.0);
+        self.set_duration(0.0);
+    }
+}
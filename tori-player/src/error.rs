@@ -0,0 +1,77 @@
+use std::{error, fmt, io};
+
+use symphonia::core::errors::Error as SymError;
+
+use crate::output::AudioOutputError;
+
+/// Errors that happen while opening, decoding or playing a track
+#[derive(Debug)]
+pub enum Error {
+    /// A local file couldn't be opened or read
+    Io(io::Error),
+    /// The format isn't supported, or the file is malformed
+    Probe(SymError),
+    /// There's no audio track with a supported codec
+    NoSupportedTrack,
+    /// A decoder couldn't be created for the track
+    Codec(SymError),
+    /// An unrecoverable error happened while reading or decoding packets
+    Decode(SymError),
+    /// The audio output device couldn't be opened or written to
+    OutputDevice(String),
+    /// yt-dlp couldn't be run, or couldn't resolve the URL
+    Ytdlp(String),
+    /// ffmpeg couldn't be started
+    Ffmpeg(io::Error),
+    /// A URL stream couldn't be demuxed or decoded
+    Stream(ac_ffmpeg::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<AudioOutputError> for Error {
+    fn from(err: AudioOutputError) -> Self {
+        let msg = match err {
+            AudioOutputError::OpenStreamError => "failed to open the audio output stream".into(),
+            AudioOutputError::PlayStreamError => "failed to start the audio output stream".into(),
+            AudioOutputError::StreamClosedError => "the audio output stream was closed".into(),
+            AudioOutputError::HostNotFound(host) => format!("audio host '{}' not found", host),
+            AudioOutputError::DeviceNotFound(device) => {
+                format!("audio device '{}' not found", device)
+            }
+            AudioOutputError::FileError(err) => format!("failed to write the audio file: {}", err),
+        };
+        Self::OutputDevice(msg)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "IO error: {}", err),
+            Self::Probe(err) => write!(f, "Unsupported format: {}", err),
+            Self::NoSupportedTrack => write!(f, "No supported audio tracks"),
+            Self::Codec(err) => write!(f, "Unsupported codec: {}", err),
+            Self::Decode(err) => write!(f, "Decoding error: {}", err),
+            Self::OutputDevice(msg) => write!(f, "Audio output error: {}", msg),
+            Self::Ytdlp(msg) => write!(f, "yt-dlp error: {}", msg),
+            Self::Ffmpeg(err) => write!(f, "Failed to run ffmpeg: {}", err),
+            Self::Stream(err) => write!(f, "Stream decoding error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io(err) | Self::Ffmpeg(err) => Some(err),
+            Self::Probe(err) | Self::Codec(err) | Self::Decode(err) => Some(err),
+            Self::Stream(err) => Some(err),
+            Self::NoSupportedTrack | Self::OutputDevice(_) | Self::Ytdlp(_) => None,
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Software volume and ReplayGain, applied to the samples before they're sent to the audio output
+
+use symphonia::core::{
+    conv::{FromSample, IntoSample},
+    meta::{StandardTagKey, Tag},
+    sample::Sample,
+};
+
+/// How long it takes for the gain to go from 0 to 1, in seconds. Changing the gain instantly
+/// causes audible clicks.
+const RAMP_SECONDS: f32 = 0.05;
+
+/// Which ReplayGain values are applied to the tracks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplayGainMode {
+    /// Normalize every track to the same loudness
+    Track,
+    /// Keep the loudness differences between the tracks of an album
+    Album,
+    #[default]
+    Off,
+}
+
+/// ReplayGain values read from a track's tags
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    pub fn from_tags<'t>(tags: impl IntoIterator<Item = &'t Tag>) -> Self {
+        let mut me = Self::default();
+        for tag in tags {
+            let value = || parse_tag_value(&tag.value.to_string());
+            match tag.std_key {
+                Some(StandardTagKey::ReplayGainTrackGain) => me.track_gain = value(),
+                Some(StandardTagKey::ReplayGainTrackPeak) => me.track_peak = value(),
+                Some(StandardTagKey::ReplayGainAlbumGain) => me.album_gain = value(),
+                Some(StandardTagKey::ReplayGainAlbumPeak) => me.album_peak = value(),
+                _ => {}
+            }
+        }
+        me
+    }
+
+    /// Linear gain for the mode. Falls back to the other mode's values if the ones for this mode
+    /// are missing, and is lowered if the track would clip otherwise.
+    pub fn factor(&self, mode: ReplayGainMode) -> f32 {
+        let track = (self.track_gain, self.track_peak);
+        let album = (self.album_gain, self.album_peak);
+        let (gain, peak) = match mode {
+            ReplayGainMode::Off => return 1.0,
+            ReplayGainMode::Track if track.0.is_some() => track,
+            ReplayGainMode::Track => album,
+            ReplayGainMode::Album if album.0.is_some() => album,
+            ReplayGainMode::Album => track,
+        };
+
+        let factor = match gain {
+            Some(db) => 10f32.powf(db / 20.0),
+            None => return 1.0,
+        };
+        match peak {
+            Some(peak) if peak > 0.0 => factor.min(1.0 / peak),
+            _ => factor,
+        }
+    }
+}
+
+/// Parses values like "-6.54 dB" or "0.988525"
+fn parse_tag_value(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let value = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value);
+    value.trim().parse().ok().filter(|x: &f32| x.is_finite())
+}
+
+/// Multiplies interleaved samples by a gain, ramping smoothly to it when it changes
+#[derive(Debug)]
+pub struct GainStage {
+    current: f32,
+    /// Maximum change of the gain between two frames
+    step: f32,
+}
+
+impl GainStage {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            current: 1.0,
+            step: 1.0 / (RAMP_SECONDS * sample_rate as f32),
+        }
+    }
+
+    pub fn apply<T>(&mut self, samples: &mut [T], channels: usize, target: f32)
+    where
+        T: Sample + FromSample<f32> + IntoSample<f32>,
+    {
+        if self.current == target {
+            if target != 1.0 {
+                for s in samples.iter_mut() {
+                    *s = scale(*s, target);
+                }
+            }
+            return;
+        }
+
+        for frame in samples.chunks_mut(channels.max(1)) {
+            let delta = target - self.current;
+            if delta.abs() <= self.step {
+                self.current = target;
+            } else {
+                self.current += self.step.copysign(delta);
+            }
+            for s in frame.iter_mut() {
+                *s = scale(*s, self.current);
+            }
+        }
+    }
+}
+
+fn scale<T>(sample: T, gain: f32) -> T
+where
+    T: Sample + FromSample<f32> + IntoSample<f32>,
+{
+    let sample: f32 = sample.into_sample();
+    T::from_sample(sample * gain)
+}
@@ -1,9 +1,16 @@
 pub mod controller;
+mod error;
+mod gain;
 mod output;
 mod resampler;
 pub mod source;
+mod tap;
 
 use controller::Controller;
+pub use error::Error;
+pub use gain::ReplayGainMode;
+pub use output::OutputKind;
+pub use tap::SampleTap;
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
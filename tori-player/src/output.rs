@@ -8,9 +8,10 @@
 
 //! Platform-dependant Audio Outputs
 
-use std::{result, time};
+use std::{io, path::PathBuf, result, sync::Arc, thread, time};
 
-use super::resampler::Resampler;
+use super::{gain::GainStage, resampler::Resampler};
+use crate::controller::SharedState;
 use symphonia::core::audio::{AudioBufferRef, RawSample, SampleBuffer, SignalSpec};
 use symphonia::core::conv::{ConvertibleSample, IntoSample};
 use symphonia::core::units::Duration;
@@ -20,9 +21,41 @@ use rb::*;
 
 use log::{error, info};
 
+mod null;
+use null::NullAudioOutput;
+
+mod wav;
+use wav::WavAudioOutput;
+
+/// Where the decoded audio goes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum OutputKind {
+    /// The sound card, through cpal
+    #[default]
+    Cpal,
+    /// Discards the audio. If `realtime` is set, it takes as long as playing it would.
+    Null { realtime: bool },
+    /// Writes the audio to a WAV file, as fast as it can be decoded. The file is overwritten every
+    /// time the output is opened, which happens when the playlist is started again.
+    Wav {
+        path: PathBuf,
+        /// Resample everything to this rate instead of using the first track's
+        sample_rate: Option<u32>,
+    },
+}
+
 pub trait AudioOutput {
     fn write(&mut self, decoded: AudioBufferRef<'_>) -> Result<()>;
     fn flush(&mut self);
+    /// Whether decoded buffers with this spec and capacity can be written to this output. If not,
+    /// a new output has to be opened.
+    fn accepts(&self, spec: SignalSpec, duration: Duration) -> bool;
+    /// Discards the samples that were written but not played yet
+    fn clear(&mut self);
+    /// Stops playing, keeping the samples that weren't played yet
+    fn pause(&mut self);
+    /// Continues playing after [pause](AudioOutput::pause). Does nothing if it isn't paused.
+    fn resume(&mut self) -> Result<()>;
 }
 
 #[allow(dead_code)]
@@ -32,6 +65,9 @@ pub enum AudioOutputError {
     OpenStreamError,
     PlayStreamError,
     StreamClosedError,
+    HostNotFound(String),
+    DeviceNotFound(String),
+    FileError(io::Error),
 }
 
 pub type Result<T> = result::Result<T, AudioOutputError>;
@@ -48,17 +84,22 @@ impl AudioOutputSample for i16 {}
 impl AudioOutputSample for u16 {}
 
 impl CpalAudioOutput {
-    pub fn try_open(spec: SignalSpec, duration: Duration) -> Result<Box<dyn AudioOutput>> {
-        // Get default host.
-        let host = cpal::default_host();
-
-        // Get the default audio output device.
-        let device = match host.default_output_device() {
-            Some(device) => device,
-            _ => {
-                error!("failed to get default audio output device");
-                return Err(AudioOutputError::OpenStreamError);
-            }
+    pub fn try_open(
+        spec: SignalSpec,
+        duration: Duration,
+        state: Arc<SharedState>,
+    ) -> Result<Box<dyn AudioOutput>> {
+        // Get the configured host and device, or the default ones.
+        let host = find_host(state.output_host().as_deref())?;
+        let device = match state.output_device() {
+            Some(name) => find_device(&host, &name)?,
+            None => match host.default_output_device() {
+                Some(device) => device,
+                _ => {
+                    error!("failed to get default audio output device");
+                    return Err(AudioOutputError::OpenStreamError);
+                }
+            },
         };
 
         let config = match device.default_output_config() {
@@ -72,27 +113,72 @@ impl CpalAudioOutput {
         // Select proper playback routine based on sample format.
         match config.sample_format() {
             cpal::SampleFormat::F32 => {
-                CpalAudioOutputImpl::<f32>::try_open(spec, duration, &device)
+                CpalAudioOutputImpl::<f32>::try_open(spec, duration, &device, state)
             }
             cpal::SampleFormat::I16 => {
-                CpalAudioOutputImpl::<i16>::try_open(spec, duration, &device)
+                CpalAudioOutputImpl::<i16>::try_open(spec, duration, &device, state)
             }
             cpal::SampleFormat::U16 => {
-                CpalAudioOutputImpl::<u16>::try_open(spec, duration, &device)
+                CpalAudioOutputImpl::<u16>::try_open(spec, duration, &device, state)
+            }
+            format => {
+                error!("unsupported audio output sample format: {}", format);
+                Err(AudioOutputError::OpenStreamError)
             }
-            _ => unimplemented!(),
         }
     }
 }
 
+/// Lists the names of the output devices of a cpal host (the default host if `None`)
+pub fn output_devices(host: Option<&str>) -> Result<Vec<String>> {
+    let host = find_host(host)?;
+    let devices = match host.output_devices() {
+        Ok(devices) => devices,
+        Err(err) => {
+            error!("failed to list audio output devices: {}", err);
+            return Err(AudioOutputError::OpenStreamError);
+        }
+    };
+    Ok(devices.filter_map(|device| device.name().ok()).collect())
+}
+
+/// Finds a cpal host by name, like "alsa" or "jack". Case-insensitive.
+fn find_host(name: Option<&str>) -> Result<cpal::Host> {
+    let name = match name {
+        Some(name) => name,
+        None => return Ok(cpal::default_host()),
+    };
+
+    cpal::available_hosts()
+        .into_iter()
+        .find(|id| id.name().eq_ignore_ascii_case(name))
+        .and_then(|id| cpal::host_from_id(id).ok())
+        .ok_or_else(|| AudioOutputError::HostNotFound(name.to_string()))
+}
+
+fn find_device(host: &cpal::Host, name: &str) -> Result<cpal::Device> {
+    host.output_devices()
+        .ok()
+        .and_then(|mut devices| devices.find(|d| d.name().ok().as_deref() == Some(name)))
+        .ok_or_else(|| AudioOutputError::DeviceNotFound(name.to_string()))
+}
+
 struct CpalAudioOutputImpl<T: AudioOutputSample>
 where
     T: AudioOutputSample,
 {
+    spec: SignalSpec,
+    duration: Duration,
+    ring_buf: rb::SpscRb<T>,
     ring_buf_producer: rb::Producer<T>,
     sample_buf: SampleBuffer<T>,
     stream: cpal::Stream,
     resampler: Option<Resampler<T>>,
+    gain: GainStage,
+    /// Samples with the gain applied, ready to be written to the ring buffer
+    gain_buf: Vec<T>,
+    state: Arc<SharedState>,
+    paused: bool,
 }
 
 impl<T: AudioOutputSample> CpalAudioOutputImpl<T> {
@@ -100,6 +186,7 @@ impl<T: AudioOutputSample> CpalAudioOutputImpl<T> {
         spec: SignalSpec,
         duration: Duration,
         device: &cpal::Device,
+        state: Arc<SharedState>,
     ) -> Result<Box<dyn AudioOutput>> {
         let num_channels = spec.channels.count();
 
@@ -112,10 +199,13 @@ impl<T: AudioOutputSample> CpalAudioOutputImpl<T> {
             }
         } else {
             // Use the default config for Windows.
-            device
-                .default_output_config()
-                .expect("Failed to get the default output config.")
-                .config()
+            match device.default_output_config() {
+                Ok(config) => config.config(),
+                Err(err) => {
+                    error!("failed to get default audio output device config: {}", err);
+                    return Err(AudioOutputError::OpenStreamError);
+                }
+            }
         };
 
         // Create a ring buffer with a capacity for up-to 200ms of audio.
@@ -124,12 +214,35 @@ impl<T: AudioOutputSample> CpalAudioOutputImpl<T> {
         let ring_buf = SpscRb::new(ring_len);
         let (ring_buf_producer, ring_buf_consumer) = (ring_buf.producer(), ring_buf.consumer());
 
+        // The played samples are also mixed down to mono and copied to the tap, if there's a
+        // visualizer reading from it
+        let tap = state.tap().clone();
+        tap.set_sample_rate(config.sample_rate.0);
+        let tap_producer = tap.producer();
+        let tap_channels = config.channels as usize;
+        let mut tap_buf: Vec<f32> = Vec::with_capacity(ring_len / num_channels);
+
+        let callback_state = state.clone();
         let stream_result = device.build_output_stream(
             &config,
             move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
                 // Write out as many samples as possible from the ring buffer to the audio
-                // output.
-                let written = ring_buf_consumer.read(data).unwrap_or(0);
+                // output. Keep the samples in the ring buffer if we're paused.
+                let written = if callback_state.paused() {
+                    0
+                } else {
+                    ring_buf_consumer.read(data).unwrap_or(0)
+                };
+
+                if tap.enabled() {
+                    tap_buf.clear();
+                    tap_buf.extend(data[..written].chunks(tap_channels).map(|frame| {
+                        let sum: f32 = frame.iter().map(|&s| s.into_sample()).sum();
+                        sum / tap_channels as f32
+                    }));
+                    // The tap is allowed to drop samples if nobody is reading them
+                    tap_producer.write(&tap_buf).ok();
+                }
 
                 // Mute any remaining samples.
                 data[written..].iter_mut().for_each(|s| *s = T::MID);
@@ -138,13 +251,14 @@ impl<T: AudioOutputSample> CpalAudioOutputImpl<T> {
             Some(time::Duration::from_secs(1)),
         );
 
-        if let Err(err) = stream_result {
-            error!("audio output stream open error: {}", err);
-
-            return Err(AudioOutputError::OpenStreamError);
-        }
+        let stream = match stream_result {
+            Ok(stream) => stream,
+            Err(err) => {
+                error!("audio output stream open error: {}", err);
 
-        let stream = stream_result.unwrap();
+                return Err(AudioOutputError::OpenStreamError);
+            }
+        };
 
         // Start the output stream.
         if let Err(err) = stream.play() {
@@ -167,14 +281,38 @@ impl<T: AudioOutputSample> CpalAudioOutputImpl<T> {
         };
 
         Ok(Box::new(CpalAudioOutputImpl {
+            spec,
+            duration,
+            ring_buf,
             ring_buf_producer,
             sample_buf,
             stream,
             resampler,
+            gain: GainStage::new(config.sample_rate.0),
+            gain_buf: Vec::new(),
+            state,
+            paused: false,
         }))
     }
 }
 
+impl<T: AudioOutputSample> CpalAudioOutputImpl<T> {
+    /// Applies volume and ReplayGain to the samples in `gain_buf` and writes them to the ring
+    /// buffer. The gain is applied here rather than in the output callback so that it's after the
+    /// resampler, and so it can ramp smoothly when it changes.
+    fn write_gain_buf(&mut self) {
+        let channels = self.spec.channels.count();
+        self.gain
+            .apply(&mut self.gain_buf, channels, self.state.gain());
+
+        // Write all samples to the ring buffer.
+        let mut samples = &self.gain_buf[..];
+        while let Some(written) = self.ring_buf_producer.write_blocking(samples) {
+            samples = &samples[written..];
+        }
+    }
+}
+
 impl<T: AudioOutputSample> AudioOutput for CpalAudioOutputImpl<T> {
     fn write(&mut self, decoded: AudioBufferRef<'_>) -> Result<()> {
         // Do nothing if there are no audio frames.
@@ -182,7 +320,7 @@ impl<T: AudioOutputSample> AudioOutput for CpalAudioOutputImpl<T> {
             return Ok(());
         }
 
-        let mut samples = if let Some(resampler) = &mut self.resampler {
+        let samples = if let Some(resampler) = &mut self.resampler {
             // Resampling is required. The resampler will return interleaved samples in the
             // correct sample format.
             match resampler.resample(decoded) {
@@ -196,10 +334,9 @@ impl<T: AudioOutputSample> AudioOutput for CpalAudioOutputImpl<T> {
             self.sample_buf.samples()
         };
 
-        // Write all samples to the ring buffer.
-        while let Some(written) = self.ring_buf_producer.write_blocking(samples) {
-            samples = &samples[written..];
-        }
+        self.gain_buf.clear();
+        self.gain_buf.extend_from_slice(samples);
+        self.write_gain_buf();
 
         Ok(())
     }
@@ -208,18 +345,67 @@ impl<T: AudioOutputSample> AudioOutput for CpalAudioOutputImpl<T> {
         // If there is a resampler, then it may need to be flushed
         // depending on the number of samples it has.
         if let Some(resampler) = &mut self.resampler {
-            let mut remaining_samples = resampler.flush().unwrap_or_default();
+            let remaining_samples = resampler.flush().unwrap_or_default();
 
-            while let Some(written) = self.ring_buf_producer.write_blocking(remaining_samples) {
-                remaining_samples = &remaining_samples[written..];
-            }
+            self.gain_buf.clear();
+            self.gain_buf.extend_from_slice(remaining_samples);
+            self.write_gain_buf();
+        }
+
+        // Let the samples that are left in the ring buffer play out, so the end of the track isn't
+        // cut off. Give up after a while in case the stream is stuck.
+        let start = time::Instant::now();
+        while !self.ring_buf.is_empty() && start.elapsed() < time::Duration::from_secs(1) {
+            thread::sleep(time::Duration::from_millis(10));
         }
 
         // Flush is best-effort, ignore the returned result.
         let _ = self.stream.pause();
     }
+
+    fn accepts(&self, spec: SignalSpec, duration: Duration) -> bool {
+        spec == self.spec && duration <= self.duration
+    }
+
+    fn clear(&mut self) {
+        if let Some(resampler) = &mut self.resampler {
+            resampler.clear();
+        }
+        self.ring_buf.clear();
+    }
+
+    fn pause(&mut self) {
+        if let Err(err) = self.stream.pause() {
+            // The output callback plays silence while paused anyway
+            error!("audio output stream pause error: {}", err);
+        }
+        self.paused = true;
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        if !self.paused {
+            return Ok(());
+        }
+        if let Err(err) = self.stream.play() {
+            error!("audio output stream play error: {}", err);
+            return Err(AudioOutputError::PlayStreamError);
+        }
+        self.paused = false;
+        Ok(())
+    }
 }
 
-pub fn try_open(spec: SignalSpec, duration: Duration) -> Result<Box<dyn AudioOutput>> {
-    CpalAudioOutput::try_open(spec, duration)
+/// Opens the output selected in the shared state
+pub fn try_open(
+    spec: SignalSpec,
+    duration: Duration,
+    state: Arc<SharedState>,
+) -> Result<Box<dyn AudioOutput>> {
+    match state.output_kind() {
+        OutputKind::Cpal => CpalAudioOutput::try_open(spec, duration, state),
+        OutputKind::Null { realtime } => Ok(Box::new(NullAudioOutput::new(realtime))),
+        OutputKind::Wav { path, sample_rate } => {
+            WavAudioOutput::create(&path, spec, sample_rate, state)
+        }
+    }
 }
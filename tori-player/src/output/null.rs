@@ -0,0 +1,59 @@
+use std::{thread, time::Instant};
+
+use symphonia::core::audio::{AudioBufferRef, SignalSpec};
+use symphonia::core::units::Duration;
+
+use super::{AudioOutput, Result};
+
+/// Discards everything that's written to it. Used to test the player without a sound card.
+pub struct NullAudioOutput {
+    /// Whether writing takes as long as playing the samples would, like a real output
+    realtime: bool,
+    /// When the samples started being "played", reset after pausing
+    start: Option<Instant>,
+    /// Seconds of audio written since `start`
+    written: f64,
+}
+
+impl NullAudioOutput {
+    pub fn new(realtime: bool) -> Self {
+        Self {
+            realtime,
+            start: None,
+            written: 0.0,
+        }
+    }
+}
+
+impl AudioOutput for NullAudioOutput {
+    fn write(&mut self, decoded: AudioBufferRef<'_>) -> Result<()> {
+        if !self.realtime || decoded.frames() == 0 {
+            return Ok(());
+        }
+
+        let start = *self.start.get_or_insert_with(Instant::now);
+        self.written += decoded.frames() as f64 / decoded.spec().rate as f64;
+        let ahead = self.written - start.elapsed().as_secs_f64();
+        if ahead > 0.0 {
+            thread::sleep(std::time::Duration::from_secs_f64(ahead));
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) {}
+
+    fn accepts(&self, _spec: SignalSpec, _duration: Duration) -> bool {
+        true
+    }
+
+    fn clear(&mut self) {}
+
+    fn pause(&mut self) {
+        self.start = None;
+        self.written = 0.0;
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
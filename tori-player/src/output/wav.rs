@@ -0,0 +1,210 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use symphonia::core::audio::{AudioBufferRef, SampleBuffer, SignalSpec};
+use symphonia::core::units::Duration;
+
+use super::{AudioOutput, AudioOutputError, Result};
+use crate::{controller::SharedState, gain::GainStage, resampler::Resampler};
+
+/// Size of the RIFF, fmt and data chunk headers
+const HEADER_LEN: u32 = 44;
+/// The RIFF chunk's size (everything after its first 8 bytes) has to fit in a u32
+const MAX_DATA_LEN: u64 = (u32::MAX - (HEADER_LEN - 8)) as u64;
+
+/// Writes everything that's played to a 32-bit float WAV file. The format of the file is fixed
+/// when it's created, and the tracks that come after are resampled and remixed to match it, so a
+/// whole playlist can be written to a single file.
+pub struct WavAudioOutput {
+    file: BufWriter<File>,
+    sample_rate: u32,
+    channels: usize,
+    /// Number of frames written so far
+    frames: u64,
+    converter: Option<Converter>,
+    gain: GainStage,
+    gain_buf: Vec<f32>,
+    state: Arc<SharedState>,
+}
+
+/// Converts the decoded buffers of a track to interleaved samples at the file's sample rate
+struct Converter {
+    spec: SignalSpec,
+    duration: Duration,
+    sample_buf: SampleBuffer<f32>,
+    resampler: Option<Resampler<f32>>,
+}
+
+impl WavAudioOutput {
+    /// Creates (or truncates) the file at `path`. The file has the same format as `spec`, except
+    /// for the sample rate if `sample_rate` is set.
+    pub fn create(
+        path: &Path,
+        spec: SignalSpec,
+        sample_rate: Option<u32>,
+        state: Arc<SharedState>,
+    ) -> Result<Box<dyn AudioOutput>> {
+        let sample_rate = sample_rate.unwrap_or(spec.rate);
+        let file = File::create(path).map_err(AudioOutputError::FileError)?;
+        let mut me = Self {
+            file: BufWriter::new(file),
+            sample_rate,
+            channels: spec.channels.count(),
+            frames: 0,
+            converter: None,
+            gain: GainStage::new(sample_rate),
+            gain_buf: Vec::new(),
+            state,
+        };
+        me.write_header().map_err(AudioOutputError::FileError)?;
+        Ok(Box::new(me))
+    }
+
+    /// Size in bytes of `frames` frames
+    fn data_len(&self, frames: u64) -> u64 {
+        frames * self.channels as u64 * 4
+    }
+
+    /// Writes the header with the current length. It's only done when flushing, because seeking
+    /// flushes the buffer.
+    fn write_header(&mut self) -> io::Result<()> {
+        let channels = self.channels as u16;
+        // `write_gain_buf` never goes over the limit
+        let data_len = self.data_len(self.frames) as u32;
+        let file = &mut self.file;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(b"RIFF")?;
+        file.write_all(&(HEADER_LEN - 8 + data_len).to_le_bytes())?;
+        file.write_all(b"WAVE")?;
+
+        file.write_all(b"fmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        file.write_all(&3u16.to_le_bytes())?; // IEEE float
+        file.write_all(&channels.to_le_bytes())?;
+        file.write_all(&self.sample_rate.to_le_bytes())?;
+        file.write_all(&(self.sample_rate * channels as u32 * 4).to_le_bytes())?; // byte rate
+        file.write_all(&(channels * 4).to_le_bytes())?; // block align
+        file.write_all(&32u16.to_le_bytes())?; // bits per sample
+
+        file.write_all(b"data")?;
+        file.write_all(&data_len.to_le_bytes())?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+
+    /// Applies the gain to `gain_buf` and appends it to the file. Fails without writing anything
+    /// if the file would get too big for a WAV header.
+    fn write_gain_buf(&mut self) -> io::Result<()> {
+        let frames = self.frames + (self.gain_buf.len() / self.channels) as u64;
+        if self.data_len(frames) > MAX_DATA_LEN {
+            return Err(io::Error::other("WAV files can't be larger than 4 GiB"));
+        }
+
+        self.gain
+            .apply(&mut self.gain_buf, self.channels, self.state.gain());
+        for s in &self.gain_buf {
+            self.file.write_all(&s.to_le_bytes())?;
+        }
+        self.frames = frames;
+        Ok(())
+    }
+
+    /// Writes the samples that are left in the resampler
+    fn flush_converter(&mut self) {
+        if let Some(converter) = self.converter.as_mut() {
+            let channels = converter.spec.channels.count();
+            if let Some(samples) = converter.resampler.as_mut().and_then(|r| r.flush()) {
+                remix(samples, channels, &mut self.gain_buf, self.channels);
+                self.write_gain_buf().ok();
+            }
+        }
+    }
+}
+
+impl AudioOutput for WavAudioOutput {
+    fn write(&mut self, decoded: AudioBufferRef<'_>) -> Result<()> {
+        if decoded.frames() == 0 {
+            return Ok(());
+        }
+
+        let spec = *decoded.spec();
+        let duration = decoded.capacity() as Duration;
+        if !matches!(&self.converter, Some(c) if c.spec == spec && c.duration >= duration) {
+            // The previous track's format is different
+            self.flush_converter();
+            let resampler = (spec.rate != self.sample_rate)
+                .then(|| Resampler::new(spec, self.sample_rate as usize, duration));
+            self.converter = Some(Converter {
+                spec,
+                duration,
+                sample_buf: SampleBuffer::new(duration, spec),
+                resampler,
+            });
+        }
+
+        let converter = self.converter.as_mut().unwrap();
+        let samples = match &mut converter.resampler {
+            Some(resampler) => match resampler.resample(decoded) {
+                Some(resampled) => resampled,
+                None => return Ok(()),
+            },
+            None => {
+                converter.sample_buf.copy_interleaved_ref(decoded);
+                converter.sample_buf.samples()
+            }
+        };
+        remix(
+            samples,
+            spec.channels.count(),
+            &mut self.gain_buf,
+            self.channels,
+        );
+        self.write_gain_buf().map_err(AudioOutputError::FileError)
+    }
+
+    fn flush(&mut self) {
+        self.flush_converter();
+        self.write_header().ok();
+        self.file.flush().ok();
+    }
+
+    fn accepts(&self, _spec: SignalSpec, _duration: Duration) -> bool {
+        // Any track can be converted to the file's format
+        true
+    }
+
+    fn clear(&mut self) {
+        if let Some(resampler) = self.converter.as_mut().and_then(|c| c.resampler.as_mut()) {
+            resampler.clear();
+        }
+    }
+
+    fn pause(&mut self) {}
+
+    fn resume(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Copies interleaved samples with `channels` channels to `out`, with `out_channels` channels
+fn remix(samples: &[f32], channels: usize, out: &mut Vec<f32>, out_channels: usize) {
+    out.clear();
+    if channels == out_channels {
+        out.extend_from_slice(samples);
+        return;
+    }
+    for frame in samples.chunks_exact(channels) {
+        out.extend((0..out_channels).map(|ch| frame[ch % channels]));
+    }
+}
+
+impl Drop for WavAudioOutput {
+    fn drop(&mut self) {
+        self.write_header().ok();
+        self.file.flush().ok();
+    }
+}
@@ -24,7 +24,7 @@ where
     T: Sample + FromSample<f32> + IntoSample<f32>,
 {
     fn resample_inner(&mut self) -> &[T] {
-        {
+        let (_, out_frames) = {
             let mut input: arrayvec::ArrayVec<&[f32], 32> = Default::default();
 
             for channel in self.input.iter() {
@@ -38,8 +38,8 @@ where
                 &mut self.output,
                 None,
             )
-            .unwrap();
-        }
+            .unwrap()
+        };
 
         // Remove consumed samples from the input buffer.
         for channel in self.input.iter_mut() {
@@ -49,8 +49,7 @@ where
         // Interleave the planar samples from Rubato.
         let num_channels = self.output.len();
 
-        self.interleaved
-            .resize(num_channels * self.output[0].len(), T::MID);
+        self.interleaved.resize(num_channels * out_frames, T::MID);
 
         for (i, frame) in self.interleaved.chunks_exact_mut(num_channels).enumerate() {
             for (ch, s) in frame.iter_mut().enumerate() {
@@ -79,7 +78,9 @@ where
         )
         .unwrap();
 
-        let output = rubato::Resampler::output_buffer_allocate(&resampler);
+        // rubato only reserves the capacity of the output buffers, but they have to be filled
+        let output_frames = rubato::Resampler::output_frames_max(&resampler);
+        let output = vec![vec![0.0; output_frames]; num_channels];
 
         let input = vec![Vec::with_capacity(duration); num_channels];
 
@@ -127,6 +128,13 @@ where
 
         Some(self.resample_inner())
     }
+
+    /// Discards any samples in the resample buffer
+    pub fn clear(&mut self) {
+        for channel in self.input.iter_mut() {
+            channel.clear();
+        }
+    }
 }
 
 fn convert_samples_any(input: &AudioBufferRef<'_>, output: &mut [Vec<f32>]) {
@@ -1,127 +1,555 @@
-use crate::Result;
-use std::{
-    fs::File,
-    io,
-    path::Path,
-    process::{Command, Stdio},
-    thread,
-};
+use std::{fs::File, io, mem, path::Path, sync::Arc, thread};
 
+use crossbeam_channel::{Receiver, Sender, TryRecvError};
+use log::error;
 use symphonia::core::{
-    codecs::{DecoderOptions, CODEC_TYPE_NULL},
+    audio::AudioBufferRef,
+    codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL},
     errors::Error as SymError,
-    formats::FormatOptions,
-    io::{MediaSource, MediaSourceStream, ReadOnlySource},
-    meta::MetadataOptions,
+    formats::{FormatOptions, FormatReader, SeekMode, SeekTo, Track},
+    io::{MediaSource, MediaSourceStream},
+    meta::{MetadataOptions, StandardTagKey, Tag},
     probe::Hint,
+    units::{Time, TimeBase},
 };
 
-use crate::output::CpalAudioOutput;
+use crate::controller::{playlist::filename, PlayerStatus, SharedState};
+use crate::gain::ReplayGain;
+use crate::output::{self, AudioOutput};
+use crate::Error;
 
-// TODO: remove `expects` and `unwraps`
-pub fn start_player_thread(path: &str) {
-    let (mss, hint) = mss_from_path(path).unwrap();
+mod stream;
+use stream::{StreamDecoder, StreamSource};
 
-    // Use the default options for metadata and format readers.
-    let meta_opts: MetadataOptions = Default::default();
-    let fmt_opts = FormatOptions {
-        enable_gapless: true,
-        ..Default::default()
-    };
+type Result<T> = std::result::Result<T, Error>;
 
-    // Probe the media source.
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &fmt_opts, &meta_opts)
-        .expect("unsupported format");
+/// Messages sent from the [Controller](crate::controller::Controller) to the player thread
+#[derive(Debug)]
+pub(crate) enum Message {
+    /// Start playing the track at the current playlist position
+    Load,
+    /// Seek in the current track
+    Seek(SeekTarget),
+    /// Stop decoding and playing until [Message::Resume] is received
+    Pause,
+    /// Continue after [Message::Pause]
+    Resume,
+    /// Close the audio output, so it's opened again on the newly selected device
+    ReopenOutput,
+    /// Stop the player thread
+    Quit,
+}
 
-    // Get the instantiated format reader.
-    let mut format = probed.format;
+/// Where to seek to
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum SeekTarget {
+    /// Seek forward (or backward, if negative) by some amount of seconds
+    Relative(f64),
+    /// Seek to a percentage of the track's duration
+    Percent(f64),
+}
 
-    // Find the first audio track with a known (decodeable) codec.
-    let track = format
-        .tracks()
-        .iter()
-        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-        .expect("no supported audio tracks");
+/// Why [play_track] returned
+enum TrackEnd {
+    Finished,
+    Interrupted(Message),
+    Disconnected,
+}
 
-    // Use the default options for the decoder.
-    let dec_opts: DecoderOptions = Default::default();
+/// Spawns the thread that reads packets from the tracks in the playlist and sends them to the
+/// decoder and then to the audio output. Errors are sent back through `errors`, and the track that
+/// failed is skipped.
+pub(crate) fn start_player_thread(
+    state: Arc<SharedState>,
+    receiver: Receiver<Message>,
+    errors: Sender<Error>,
+) {
+    thread::spawn(move || {
+        let mut audio_output = None;
+        let mut preload: Option<Preload> = None;
+        // Tracks that failed in a row. When the playlist loops, it stops once all of them did.
+        let mut failures = 0;
 
-    // Create a decoder for the track.
-    let mut decoder = symphonia::default::get_codecs()
-        .make(&track.codec_params, &dec_opts)
-        .expect("unsupported codec");
+        // Waiting for something to play. The status is Idle, Stopped or Errored here.
+        while let Ok(message) = receiver.recv() {
+            match message {
+                Message::ReopenOutput => audio_output = None,
+                Message::Quit => return,
+                // Pausing while nothing is playing makes the next track start paused, which is
+                // handled by play_track
+                Message::Pause | Message::Resume | Message::Seek(_) | Message::Load => {}
+            }
 
-    // Store the track identifier, it will be used to filter packets.
-    let track_id = track.id;
+            let mut message = Some(message);
+            while let Some(Message::Load) = message.take() {
+                let path = match state.playlist().current() {
+                    Some(track) => track.path.clone(),
+                    None => {
+                        state.set_status(PlayerStatus::Stopped);
+                        break;
+                    }
+                };
+                state.set_status(PlayerStatus::Loading);
 
-    // Spawn a thread to read packets from the format reader and send them to the decoder.
-    // TODO: if the user pauses the player, this thread continues to run. Should this really be the
-    // case?
-    thread::spawn(move || {
-        let mut audio_output = None;
-        loop {
-            let packet = match format.next_packet() {
+                // Use the preloaded track if it's still the one that should be played, since the
+                // playlist may have changed in the meantime
+                let track = match preload.take().and_then(|p| p.take(&path)) {
+                    Some(track) => Ok(track),
+                    None => TrackDecoder::open(&path, 0.0),
+                };
+
+                // Open the next track while this one plays, so it's ready to be decoded as soon
+                // as this one ends
+                let next = if state.loop_file() {
+                    Some(path.clone())
+                } else {
+                    state.playlist().peek_next().map(|t| t.path.clone())
+                };
+                preload = next.map(Preload::start);
+
+                let result =
+                    track.and_then(|track| play_track(track, &state, &receiver, &mut audio_output));
+                if result.is_ok() {
+                    failures = 0;
+                }
+                match result {
+                    Ok(TrackEnd::Finished) => {
+                        if state.loop_file() || state.playlist().advance() {
+                            message = Some(Message::Load);
+                        } else {
+                            // Let the end of the last track play, and close the output. WAV files
+                            // are only complete after this.
+                            if let Some(mut audio_output) = audio_output.take() {
+                                audio_output.flush();
+                            }
+                            state.set_status(PlayerStatus::Stopped);
+                        }
+                    }
+                    Ok(TrackEnd::Interrupted(Message::Quit)) | Ok(TrackEnd::Disconnected) => return,
+                    Ok(TrackEnd::Interrupted(other)) => message = Some(other),
+                    Err(err) => {
+                        error!("failed to play {}: {}", path, err);
+                        if errors.send(err).is_err() {
+                            return;
+                        }
+                        // The output may be in a bad state, so a new one is opened next time
+                        audio_output = None;
+                        failures += 1;
+                        let advanced = {
+                            let mut playlist = state.playlist();
+                            let all_failed = failures >= playlist.tracks.len();
+                            if all_failed {
+                                playlist.position = None;
+                            }
+                            !all_failed && playlist.advance()
+                        };
+                        if advanced {
+                            message = Some(Message::Load);
+                        } else {
+                            state.set_status(PlayerStatus::Errored);
+                        }
+                    }
+                }
+            }
+
+            if state.playlist().current().is_none() {
+                state.clear_track();
+            }
+        }
+    });
+}
+
+/// A track that's being opened in the background
+struct Preload {
+    path: String,
+    handle: thread::JoinHandle<Option<TrackDecoder>>,
+}
+
+impl Preload {
+    fn start(path: String) -> Self {
+        let handle = {
+            let path = path.clone();
+            thread::spawn(move || match TrackDecoder::open(&path, 0.0) {
+                Ok(track) => Some(track),
+                Err(err) => {
+                    error!("failed to preload {}: {}", path, err);
+                    None
+                }
+            })
+        };
+        Self { path, handle }
+    }
+
+    /// Waits for the track to be opened. Returns `None` if it isn't the track at `path` or if it
+    /// couldn't be opened, in which case it should be opened again by the caller.
+    fn take(self, path: &str) -> Option<TrackDecoder> {
+        if self.path != path {
+            return None;
+        }
+        self.handle.join().ok().flatten()
+    }
+}
+
+/// Plays a single track, until it finishes or the controller sends a new message
+fn play_track(
+    mut track: TrackDecoder,
+    state: &Arc<SharedState>,
+    receiver: &Receiver<Message>,
+    audio_output: &mut Option<Box<dyn AudioOutput>>,
+) -> Result<TrackEnd> {
+    set_title(state, &track.title);
+    state.set_replay_gain(track.replay_gain());
+    state.set_duration(track.duration.unwrap_or_default());
+    state.set_time_pos(0.0);
+    state.set_status(PlayerStatus::Playing);
+
+    loop {
+        // Handle messages from the controller
+        match receiver.try_recv() {
+            // The paused flag was already set by the controller, so it's handled below
+            Ok(Message::Pause) | Ok(Message::Resume) | Err(TryRecvError::Empty) => {}
+            Ok(Message::ReopenOutput) => *audio_output = None,
+            Ok(Message::Seek(target)) => {
+                if seek(&mut track, target, state, audio_output) == SeekResult::PastTheEnd {
+                    return Ok(TrackEnd::Finished);
+                }
+            }
+            Ok(message) => return Ok(TrackEnd::Interrupted(message)),
+            Err(TryRecvError::Disconnected) => return Ok(TrackEnd::Disconnected),
+        }
+
+        // Stop decoding and playing until we're resumed
+        if state.paused() {
+            state.set_status(PlayerStatus::Paused);
+            if let Some(audio_output) = audio_output.as_mut() {
+                audio_output.pause();
+            }
+
+            while state.paused() {
+                match receiver.recv() {
+                    Ok(Message::Pause) | Ok(Message::Resume) => {}
+                    Ok(Message::ReopenOutput) => *audio_output = None,
+                    Ok(Message::Seek(target)) => {
+                        if seek(&mut track, target, state, audio_output) == SeekResult::PastTheEnd {
+                            return Ok(TrackEnd::Finished);
+                        }
+                    }
+                    Ok(message) => return Ok(TrackEnd::Interrupted(message)),
+                    Err(_) => return Ok(TrackEnd::Disconnected),
+                }
+            }
+
+            state.set_status(PlayerStatus::Playing);
+        }
+
+        // The output may have been paused by a previous track
+        if let Some(audio_output) = audio_output.as_mut() {
+            audio_output.resume()?;
+        }
+
+        let (decoded, time) = match track.next_chunk()? {
+            Some(chunk) => chunk,
+            None => return Ok(TrackEnd::Finished),
+        };
+
+        // Get the audio buffer specification. This is a description of the decoded audio buffer's
+        // sample format and sample rate.
+        let spec = *decoded.spec();
+
+        // Get the capacity of the decoded buffer. Note that this is capacity, not length! The
+        // capacity of the decoded buffer is constant for the life of the decoder, but the length
+        // is not.
+        let duration = decoded.capacity() as u64;
+
+        // The same output is kept between tracks, so they play without a gap. It has to be
+        // reopened if the new track has a different spec.
+        if let Some(output) = audio_output.as_mut() {
+            if !output.accepts(spec, duration) {
+                output.flush();
+                *audio_output = None;
+            }
+        }
+
+        // If the audio output is not open, try to open it.
+        if audio_output.is_none() {
+            audio_output.replace(output::try_open(spec, duration, state.clone())?);
+        }
+
+        if let Some(time) = time {
+            state.set_time_pos(time);
+        }
+
+        if let Some(audio_output) = audio_output.as_mut() {
+            audio_output.write(decoded)?
+        }
+
+        if let Some(title) = track.take_new_title() {
+            set_title(state, &title);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum SeekResult {
+    Done,
+    /// The target is after the end of the track
+    PastTheEnd,
+}
+
+/// Seeks in the track. Failing to seek isn't fatal, the track just continues from where it was.
+fn seek(
+    track: &mut TrackDecoder,
+    target: SeekTarget,
+    state: &SharedState,
+    audio_output: &mut Option<Box<dyn AudioOutput>>,
+) -> SeekResult {
+    let time = match target {
+        SeekTarget::Relative(seconds) => state.time_pos() + seconds,
+        SeekTarget::Percent(percent) => track.duration.unwrap_or_default() * percent / 100.0,
+    }
+    .max(0.0);
+
+    if matches!(track.duration, Some(duration) if time >= duration) {
+        return SeekResult::PastTheEnd;
+    }
+
+    match track.seek(time) {
+        Ok(()) => {
+            // Discard the audio that was decoded before seeking, so the jump is heard right away
+            if let Some(audio_output) = audio_output.as_mut() {
+                audio_output.clear();
+            }
+            state.set_time_pos(time);
+        }
+        Err(err) => error!("failed to seek to {:.1}s: {}", time, err),
+    }
+    SeekResult::Done
+}
+
+/// A track that has been opened and probed, and is ready to be decoded
+struct TrackDecoder {
+    path: String,
+    backend: Backend,
+    duration: Option<f64>,
+    title: String,
+}
+
+/// Local files are decoded by symphonia. URL streams are decoded by FFmpeg, since symphonia
+/// doesn't support Opus, which is what most of them use.
+enum Backend {
+    Symphonia(SymphoniaDecoder),
+    Ffmpeg(StreamDecoder),
+}
+
+impl TrackDecoder {
+    /// Opens the track at `path`, starting from `start` seconds if it's a URL stream
+    fn open(path: &str, start: f64) -> Result<Self> {
+        if let Some(stream) = StreamSource::resolve(path)? {
+            let decoder = StreamDecoder::open(&stream.urls, start)?;
+            return Ok(Self {
+                path: path.to_string(),
+                backend: Backend::Ffmpeg(decoder),
+                duration: stream.duration,
+                title: filename(path),
+            });
+        }
+
+        let (decoder, probed_title) = SymphoniaDecoder::open(path)?;
+        Ok(Self {
+            path: path.to_string(),
+            duration: decoder.duration(),
+            backend: Backend::Symphonia(decoder),
+            title: probed_title.unwrap_or_else(|| filename(path)),
+        })
+    }
+
+    /// Decodes the next chunk of audio, and returns it along with its position in seconds.
+    /// Returns `None` at the end of the track.
+    fn next_chunk(&mut self) -> Result<Option<(AudioBufferRef<'_>, Option<f64>)>> {
+        match &mut self.backend {
+            Backend::Symphonia(decoder) => decoder.next_chunk(),
+            Backend::Ffmpeg(decoder) => decoder.next_chunk(),
+        }
+    }
+
+    fn replay_gain(&self) -> ReplayGain {
+        match &self.backend {
+            Backend::Symphonia(decoder) => decoder.replay_gain,
+            Backend::Ffmpeg(_) => ReplayGain::default(),
+        }
+    }
+
+    /// Returns the new title of the track, if the metadata changed since the last call
+    fn take_new_title(&mut self) -> Option<String> {
+        let title = match &mut self.backend {
+            Backend::Symphonia(decoder) => decoder.new_title.take()?,
+            Backend::Ffmpeg(_) => return None,
+        };
+        self.title = title.clone();
+        Some(title)
+    }
+
+    /// Seeks to `time` seconds since the start of the track
+    fn seek(&mut self, time: f64) -> Result<()> {
+        match &mut self.backend {
+            Backend::Symphonia(decoder) => decoder.seek(time),
+            Backend::Ffmpeg(_) => {
+                // Piped streams can't be seeked, so ffmpeg is restarted from the new position
+                let reopened = Self::open(&self.path, time)?;
+                *self = Self {
+                    title: mem::take(&mut self.title),
+                    ..reopened
+                };
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Decodes local files with symphonia
+struct SymphoniaDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    time_base: Option<TimeBase>,
+    n_frames: Option<u64>,
+    /// Time at which the current stream starts. It's not zero after the stream of a chained OGG
+    /// physical stream changes.
+    start: f64,
+    /// Position of the last decoded packet, in seconds
+    time: f64,
+    /// Packets with timestamps before this one are decoded, but not played
+    seek_ts: u64,
+    /// Title found in the metadata since the track was opened
+    new_title: Option<String>,
+    replay_gain: ReplayGain,
+}
+
+impl SymphoniaDecoder {
+    /// Opens and probes a local file. Also returns the title found while probing, if any.
+    fn open(path: &str) -> Result<(Self, Option<String>)> {
+        let mut hint = Hint::new();
+        if let Some(ext) = Path::new(path).extension().and_then(|s| s.to_str()) {
+            hint.with_extension(ext);
+        }
+        let src: Box<dyn MediaSource> = Box::new(File::open(path)?);
+        let mss = MediaSourceStream::new(src, Default::default());
+
+        // Use the default options for metadata and format readers.
+        let meta_opts: MetadataOptions = Default::default();
+        let fmt_opts = FormatOptions {
+            enable_gapless: true,
+            ..Default::default()
+        };
+
+        // Probe the media source.
+        let mut probed = symphonia::default::get_probe()
+            .format(&hint, mss, &fmt_opts, &meta_opts)
+            .map_err(Error::Probe)?;
+
+        // Get the instantiated format reader.
+        let mut format = probed.format;
+
+        let track = first_supported_track(format.as_ref())?;
+        let decoder = make_decoder(track)?;
+
+        // Store the track identifier, it will be used to filter packets.
+        let track_id = track.id;
+
+        let time_base = track.codec_params.time_base;
+        let n_frames = track.codec_params.n_frames;
+
+        // Metadata may be found while probing (like ID3v2 tags) or in the container itself
+        let probed_tags = probed
+            .metadata
+            .get()
+            .as_ref()
+            .and_then(|m| m.current())
+            .map(|rev| rev.tags().to_vec())
+            .unwrap_or_default();
+        let format_tags = format
+            .metadata()
+            .current()
+            .map(|rev| rev.tags().to_vec())
+            .unwrap_or_default();
+        let title = title_from_tags(&probed_tags).or_else(|| title_from_tags(&format_tags));
+        let replay_gain = ReplayGain::from_tags(format_tags.iter().chain(&probed_tags));
+
+        let decoder = Self {
+            format,
+            decoder,
+            track_id,
+            time_base,
+            n_frames,
+            start: 0.0,
+            time: 0.0,
+            seek_ts: 0,
+            new_title: None,
+            replay_gain,
+        };
+        Ok((decoder, title))
+    }
+
+    fn duration(&self) -> Option<f64> {
+        self.time_base
+            .zip(self.n_frames)
+            .map(|(tb, n)| seconds(tb.calc_time(n)))
+    }
+
+    fn next_chunk(&mut self) -> Result<Option<(AudioBufferRef<'_>, Option<f64>)>> {
+        let time = loop {
+            let packet = match self.format.next_packet() {
                 Ok(packet) => packet,
                 Err(SymError::ResetRequired) => {
-                    // The track list has been changed. Re-examine it and create a new set of decoders,
-                    // then restart the decode loop. This is an advanced feature and it is not
-                    // unreasonable to consider this "the end." As of v0.5.0, the only usage of this is
-                    // for chained OGG physical streams.
-                    unimplemented!();
+                    // The track list has been changed. Re-examine it and create a new set of
+                    // decoders, then restart the decode loop. As of v0.5.0, the only usage of
+                    // this is for chained OGG physical streams, which many internet radios use.
+                    self.reset()?;
+                    continue;
                 }
                 Err(SymError::IoError(e))
                     if e.kind() == io::ErrorKind::UnexpectedEof
                         && e.to_string() == "end of stream" =>
                 {
                     // File ended
-                    break;
+                    return Ok(None);
                 }
                 Err(err) => {
                     // A unrecoverable error occurred, halt decoding.
-                    panic!("{}", err);
+                    return Err(Error::Decode(err));
                 }
             };
 
-            // Consume any new metadata that has been read since the last packet.
-            while !format.metadata().is_latest() {
+            // Consume any new metadata that has been read since the last packet. The new stream
+            // of a chained OGG physical stream brings its own metadata, which is usually the next
+            // song.
+            while !self.format.metadata().is_latest() {
                 // Pop the old head of the metadata queue.
-                format.metadata().pop();
+                self.format.metadata().pop();
 
                 // Consume the new metadata at the head of the metadata queue.
-                eprintln!("Got new metadata! {:?}", format.metadata().current());
+                if let Some(title) = self
+                    .format
+                    .metadata()
+                    .current()
+                    .and_then(|rev| title_from_tags(rev.tags()))
+                {
+                    self.new_title = Some(title);
+                }
             }
 
             // If the packet does not belong to the selected track, skip over it.
-            if packet.track_id() != track_id {
+            if packet.track_id() != self.track_id {
                 continue;
             }
 
-            match decoder.decode(&packet) {
-                Ok(decoded) => {
-                    // If the audio output is not open, try to open it.
-                    if audio_output.is_none() {
-                        // Get the audio buffer specification. This is a description of the decoded
-                        // audio buffer's sample format and sample rate.
-                        let spec = *decoded.spec();
-
-                        // Get the capacity of the decoded buffer. Note that this is capacity, not
-                        // length! The capacity of the decoded buffer is constant for the life of the
-                        // decoder, but the length is not.
-                        let duration = decoded.capacity() as u64;
-
-                        // Try to open the audio output.
-                        audio_output.replace(CpalAudioOutput::try_open(spec, duration).unwrap());
-                    } else {
-                        // TODO: Check the audio spec. and duration hasn't changed.
-                    }
-
-                    // Write the decoded audio samples to the audio output if the presentation timestamp
-                    // for the packet is >= the seeked position (0 if not seeking).
-                    if let Some(audio_output) = audio_output.as_mut() {
-                        audio_output.write(decoded).unwrap()
-                    }
-                }
+            // Only play the decoded audio if the presentation timestamp for the packet is >= the
+            // seeked position (0 if not seeking).
+            let play = packet.ts() >= self.seek_ts;
+            let packet_time = self.packet_time(packet.ts());
+            match self.decoder.decode(&packet) {
+                Ok(_) if play => break packet_time,
+                Ok(_) => continue,
                 Err(SymError::IoError(_)) => {
                     // The packet failed to decode due to an IO error, skip the packet.
                     continue;
@@ -132,55 +560,93 @@ pub fn start_player_thread(path: &str) {
                 }
                 Err(err) => {
                     // An unrecoverable error occurred, halt decoding.
-                    panic!("{}", err);
+                    return Err(Error::Decode(err));
                 }
             }
+        };
+
+        if let Some(time) = time {
+            self.time = time;
         }
-    });
+        Ok(Some((self.decoder.last_decoded(), time)))
+    }
+
+    /// Re-reads the track list and creates a new decoder after the format reader returned
+    /// `ResetRequired`. The new stream starts where the last one stopped, so the position keeps
+    /// increasing.
+    fn reset(&mut self) -> Result<()> {
+        let track = first_supported_track(self.format.as_ref())?;
+        let decoder = make_decoder(track)?;
+        let track_id = track.id;
+        let time_base = track.codec_params.time_base;
+
+        self.decoder = decoder;
+        self.track_id = track_id;
+        self.time_base = time_base;
+        self.n_frames = None;
+        self.start = self.time;
+        self.seek_ts = 0;
+        Ok(())
+    }
+
+    /// Converts a packet timestamp to seconds since the start of the track
+    fn packet_time(&self, ts: u64) -> Option<f64> {
+        self.time_base
+            .map(|tb| self.start + seconds(tb.calc_time(ts)))
+    }
+
+    /// Seeks to `time` seconds since the start of the track
+    fn seek(&mut self, time: f64) -> Result<()> {
+        let seeked_to = self
+            .format
+            .seek(
+                SeekMode::Accurate,
+                SeekTo::Time {
+                    time: Time::from((time - self.start).max(0.0)),
+                    track_id: Some(self.track_id),
+                },
+            )
+            .map_err(Error::Decode)?;
+
+        // The decoder must be reset after seeking
+        self.decoder.reset();
+        self.seek_ts = seeked_to.required_ts;
+        Ok(())
+    }
 }
 
-fn mss_from_path(mut path: &str) -> Result<(MediaSourceStream, Hint)> {
-    let mut force_ytdlp = false;
-    if let Some(url) = path.strip_prefix("ytdlp://") {
-        path = url;
-        force_ytdlp = true;
-    }
-
-    let mut hint = Hint::default();
-    let src: Box<dyn MediaSource> =
-        if force_ytdlp || path.starts_with("http://") || path.starts_with("https://") {
-            // Get urls from yt-dlp
-            let ytdlp_output = Command::new("yt-dlp")
-                .args(["-g", path])
-                .output()
-                .unwrap()
-                .stdout;
-            let ytdlp_output = String::from_utf8(ytdlp_output).unwrap();
-            let ytdlp_urls = ytdlp_output.lines();
-
-            // Get ffmpeg mpegts stream.
-            let mut ffmpeg = Command::new("ffmpeg");
-            for url in ytdlp_urls {
-                ffmpeg.args(["-i", url]);
-            }
-            ffmpeg
-                .args(["-f", "mp3"]) // FIXME: don't do this. If you know how to do better please tell me how. Symphonia still doesn't support opus afaik.
-                .arg("-")
-                .stdout(Stdio::piped())
-                .stderr(Stdio::null())
-                .stdin(Stdio::null());
-            let mut ffmpeg = ffmpeg.spawn().unwrap();
-            let src = ffmpeg.stdout.take().unwrap();
-
-            hint.with_extension("mp3");
-            Box::new(ReadOnlySource::new(src))
-        } else {
-            if let Some(ext) = Path::new(path).extension().and_then(|s| s.to_str()) {
-                hint.with_extension(ext);
-            }
-            Box::new(File::open(path).expect("failed to open media"))
-        };
+/// Finds the first audio track with a known (decodeable) codec
+fn first_supported_track(format: &dyn FormatReader) -> Result<&Track> {
+    format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or(Error::NoSupportedTrack)
+}
+
+/// Creates a decoder for the track, with the default options
+fn make_decoder(track: &Track) -> Result<Box<dyn Decoder>> {
+    let dec_opts: DecoderOptions = Default::default();
+    symphonia::default::get_codecs()
+        .make(&track.codec_params, &dec_opts)
+        .map_err(Error::Codec)
+}
+
+/// Sets the title of the current track, both in the playlist and as the media title
+fn set_title(state: &SharedState, title: &str) {
+    if let Some(current) = state.playlist().current_mut() {
+        current.title = Some(title.to_string());
+    }
+    state.set_media_title(title.to_string());
+}
+
+/// Builds a media title from the tags, like mpv's `media-title`
+fn title_from_tags(tags: &[Tag]) -> Option<String> {
+    tags.iter()
+        .find(|tag| tag.std_key == Some(StandardTagKey::TrackTitle))
+        .map(|tag| tag.value.to_string())
+}
 
-    let mss = MediaSourceStream::new(src, Default::default());
-    Ok((mss, hint))
+fn seconds(time: Time) -> f64 {
+    time.seconds as f64 + time.frac
 }
@@ -0,0 +1,247 @@
+//! URL streams, decoded with FFmpeg.
+//!
+//! yt-dlp resolves the URL into the actual media streams, and an ffmpeg process remuxes them
+//! into a single Matroska stream on its stdout without re-encoding anything. The original
+//! Opus/AAC/Vorbis audio is then decoded in-process with ac-ffmpeg.
+
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+use ac_ffmpeg::{
+    codec::{
+        audio::{frame::get_sample_format, AudioDecoder, AudioFrame, AudioResampler},
+        Decoder,
+    },
+    format::{
+        demuxer::{Demuxer, InputFormat},
+        io::IO,
+    },
+};
+use log::warn;
+use symphonia::core::audio::{
+    AsAudioBufferRef, AudioBuffer, AudioBufferRef, Channels, Signal, SignalSpec,
+};
+
+use super::Result;
+use crate::Error;
+
+/// Number of samples (per channel) in each chunk of decoded audio
+const CHUNK_SAMPLES: usize = 1024;
+
+/// A URL, resolved by yt-dlp
+pub struct StreamSource {
+    pub urls: Vec<String>,
+    /// Duration reported by yt-dlp, since piped streams don't know their own duration
+    pub duration: Option<f64>,
+}
+
+impl StreamSource {
+    /// Resolves `path` with yt-dlp if it's a URL, or if it starts with `ytdlp://`. Returns `None`
+    /// for local files.
+    pub fn resolve(path: &str) -> Result<Option<Self>> {
+        let url = match path.strip_prefix("ytdlp://") {
+            Some(url) => url,
+            None if path.starts_with("http://") || path.starts_with("https://") => path,
+            None => return Ok(None),
+        };
+
+        // Get duration and urls from yt-dlp
+        let output = Command::new("yt-dlp")
+            .args(["--print", "duration", "--print", "urls", url])
+            .output()
+            .map_err(|e| Error::Ytdlp(format!("failed to run yt-dlp: {}", e)))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let msg = stderr
+                .lines()
+                .last()
+                .unwrap_or("yt-dlp exited with an error");
+            return Err(Error::Ytdlp(msg.to_string()));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut lines = stdout.lines();
+        let duration = lines.next().and_then(|d| d.parse::<f64>().ok());
+        let urls: Vec<String> = lines.map(String::from).collect();
+        if urls.is_empty() {
+            return Err(Error::Ytdlp(format!("no streams found for {}", url)));
+        }
+
+        Ok(Some(Self { urls, duration }))
+    }
+}
+
+/// Decodes the audio that ffmpeg pipes to us
+pub struct StreamDecoder {
+    ffmpeg: Child,
+    demuxer: Demuxer<ChildStdout>,
+    stream_index: usize,
+    decoder: AudioDecoder,
+    /// Converts the decoded frames to planar f32, in chunks of [CHUNK_SAMPLES]. Created when the
+    /// first frame is decoded, since that's when we know its format for sure.
+    resampler: Option<AudioResampler>,
+    buffer: Option<AudioBuffer<f32>>,
+    /// Position of the next chunk, in seconds
+    time: Option<f64>,
+    /// Time at which the stream starts, since ffmpeg is restarted with `-ss` to seek
+    start: f64,
+    /// Whether the demuxer reached the end of the stream and the decoder was flushed
+    decoder_flushed: bool,
+    resampler_flushed: bool,
+}
+
+impl StreamDecoder {
+    /// Starts ffmpeg on the stream urls, from `start` seconds
+    pub fn open(urls: &[String], start: f64) -> Result<Self> {
+        let mut ffmpeg = Command::new("ffmpeg");
+        for url in urls {
+            if start > 0.0 {
+                ffmpeg.args(["-ss", &format!("{:.3}", start)]);
+            }
+            ffmpeg.args(["-i", url]);
+        }
+        ffmpeg
+            .args(["-vn", "-c:a", "copy", "-f", "matroska", "-"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .stdin(Stdio::null());
+        let mut ffmpeg = ffmpeg.spawn().map_err(Error::Ffmpeg)?;
+        let stdout = ffmpeg.stdout.take().expect("ffmpeg's stdout is piped");
+
+        let demuxer = Demuxer::builder()
+            .input_format(InputFormat::find_by_name("matroska"))
+            .build(IO::from_read_stream(stdout))
+            .map_err(Error::Stream)?
+            .find_stream_info(None)
+            .map_err(|(_, err)| Error::Stream(err))?;
+
+        let (stream_index, stream) = demuxer
+            .streams()
+            .iter()
+            .enumerate()
+            .find(|(_, stream)| stream.codec_parameters().is_audio_codec())
+            .ok_or(Error::NoSupportedTrack)?;
+
+        let decoder = AudioDecoder::from_stream(stream)
+            .and_then(|builder| builder.build())
+            .map_err(Error::Stream)?;
+
+        Ok(Self {
+            ffmpeg,
+            demuxer: demuxer.into_demuxer(),
+            stream_index,
+            decoder,
+            resampler: None,
+            buffer: None,
+            time: None,
+            start,
+            decoder_flushed: false,
+            resampler_flushed: false,
+        })
+    }
+
+    /// Decodes the next chunk of audio, and returns it along with its position in seconds.
+    /// Returns `None` at the end of the stream.
+    pub fn next_chunk(&mut self) -> Result<Option<(AudioBufferRef<'_>, Option<f64>)>> {
+        loop {
+            // Chunks that are ready to be played
+            if let Some(resampler) = self.resampler.as_mut() {
+                if let Some(frame) = resampler.take().map_err(Error::Stream)? {
+                    return Ok(Some(self.convert(frame)));
+                }
+            }
+
+            // Frames that are waiting to be resampled
+            if let Some(frame) = self.decoder.take().map_err(Error::Stream)? {
+                if self.time.is_none() {
+                    self.time = Some(self.start + frame.pts().as_f64().unwrap_or_default());
+                }
+                let resampler = match self.resampler.as_mut() {
+                    Some(resampler) => resampler,
+                    None => self.resampler.insert(make_resampler(&frame)?),
+                };
+                resampler.push(frame).map_err(Error::Stream)?;
+                continue;
+            }
+
+            if self.decoder_flushed {
+                // Everything was decoded, so we only need the last few samples in the resampler
+                match self.resampler.as_mut() {
+                    Some(resampler) if !self.resampler_flushed => {
+                        resampler.flush().map_err(Error::Stream)?;
+                        self.resampler_flushed = true;
+                        continue;
+                    }
+                    _ => return Ok(None),
+                }
+            }
+
+            match self.demuxer.take().map_err(Error::Stream)? {
+                Some(packet) if packet.stream_index() == self.stream_index => {
+                    if let Err(err) = self.decoder.push(packet) {
+                        // The packet failed to decode due to invalid data, skip the packet.
+                        warn!("skipping a packet that failed to decode: {}", err);
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    self.decoder.flush().map_err(Error::Stream)?;
+                    self.decoder_flushed = true;
+                }
+            }
+        }
+    }
+
+    /// Copies a resampled frame into a symphonia buffer, so it can be written to the audio output
+    fn convert(&mut self, frame: AudioFrame) -> (AudioBufferRef<'_>, Option<f64>) {
+        let channels = frame.channel_layout().channels() as usize;
+        let rate = frame.sample_rate();
+        let samples = frame.samples();
+
+        let spec = SignalSpec::new(rate, channels_from_count(channels));
+        if matches!(&self.buffer, Some(buffer) if *buffer.spec() != spec) {
+            self.buffer = None;
+        }
+        let buffer = self
+            .buffer
+            .get_or_insert_with(|| AudioBuffer::new(CHUNK_SAMPLES as u64, spec));
+
+        buffer.clear();
+        buffer.render_reserved(Some(samples));
+        for (ch, plane) in frame.planes().iter().enumerate().take(channels) {
+            let data = &plane.data()[..samples * 4];
+            for (dst, src) in buffer.chan_mut(ch).iter_mut().zip(data.chunks_exact(4)) {
+                *dst = f32::from_ne_bytes([src[0], src[1], src[2], src[3]]);
+            }
+        }
+
+        let time = self.time;
+        self.time = time.map(|t| t + samples as f64 / rate as f64);
+        (buffer.as_audio_buffer_ref(), time)
+    }
+}
+
+impl Drop for StreamDecoder {
+    fn drop(&mut self) {
+        self.ffmpeg.kill().ok();
+        self.ffmpeg.wait().ok();
+    }
+}
+
+/// Creates a resampler that only converts frames like `frame` to planar f32, in fixed-size chunks
+fn make_resampler(frame: &AudioFrame) -> Result<AudioResampler> {
+    AudioResampler::builder()
+        .source_channel_layout(frame.channel_layout().to_owned())
+        .source_sample_format(frame.sample_format())
+        .source_sample_rate(frame.sample_rate())
+        .target_channel_layout(frame.channel_layout().to_owned())
+        .target_sample_format(get_sample_format("fltp"))
+        .target_sample_rate(frame.sample_rate())
+        .target_frame_samples(Some(CHUNK_SAMPLES))
+        .build()
+        .map_err(Error::Stream)
+}
+
+/// Channels for the symphonia buffer. Only the count matters to the audio output.
+fn channels_from_count(count: usize) -> Channels {
+    Channels::from_bits_truncate((1u32 << count) - 1)
+}
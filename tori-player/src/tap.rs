@@ -0,0 +1,86 @@
+//! A copy of the samples that are being played, for visualizers
+
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc,
+};
+
+use rb::{Consumer, Producer, RbConsumer, SpscRb, RB};
+
+/// Number of (mono) samples kept in the tap, about half a second at 48kHz
+const TAP_LEN: usize = 1 << 15;
+
+/// Ring buffer that the audio output callback writes the played samples to, mixed down to mono.
+/// Nothing is written unless a [SampleTap] exists.
+pub(crate) struct TapBuffer {
+    rb: SpscRb<f32>,
+    sample_rate: AtomicU32,
+    enabled: AtomicBool,
+}
+
+impl Default for TapBuffer {
+    fn default() -> Self {
+        Self {
+            rb: SpscRb::new(TAP_LEN),
+            sample_rate: AtomicU32::new(0),
+            enabled: AtomicBool::new(false),
+        }
+    }
+}
+
+impl std::fmt::Debug for TapBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TapBuffer")
+            .field("sample_rate", &self.sample_rate)
+            .field("enabled", &self.enabled)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TapBuffer {
+    pub fn producer(&self) -> Producer<f32> {
+        self.rb.producer()
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_sample_rate(&self, rate: u32) {
+        self.sample_rate.store(rate, Ordering::Relaxed);
+    }
+}
+
+/// Reads the samples that are being played, mixed down to mono. Only one tap should exist at a
+/// time.
+pub struct SampleTap {
+    buffer: Arc<TapBuffer>,
+    consumer: Consumer<f32>,
+}
+
+impl SampleTap {
+    pub(crate) fn new(buffer: Arc<TapBuffer>) -> Self {
+        let consumer = buffer.rb.consumer();
+        // Old samples would show up as a burst when the visualizer starts
+        consumer.skip_pending().ok();
+        buffer.enabled.store(true, Ordering::Relaxed);
+        Self { buffer, consumer }
+    }
+
+    /// Reads the samples played since the last call into `buf`, without blocking. Returns how many
+    /// were read.
+    pub fn read(&mut self, buf: &mut [f32]) -> usize {
+        self.consumer.read(buf).unwrap_or(0)
+    }
+
+    /// Sample rate of the output device, or zero if nothing was played yet
+    pub fn sample_rate(&self) -> u32 {
+        self.buffer.sample_rate.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for SampleTap {
+    fn drop(&mut self) {
+        self.buffer.enabled.store(false, Ordering::Relaxed);
+    }
+}
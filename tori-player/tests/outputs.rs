@@ -0,0 +1,203 @@
+//! Plays generated tracks to the null and WAV outputs, so the decoding, seeking and resampling can
+//! be checked without a sound card.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use tori_player::{
+    controller::{Controller, PlayerStatus},
+    OutputKind,
+};
+
+const RATE: u32 = 44100;
+
+/// Writes a 16-bit stereo WAV file with a 440Hz sine wave
+fn write_sine(path: &Path, rate: u32, seconds: f64) {
+    let frames = (rate as f64 * seconds) as u32;
+    let data_len = frames * 4;
+    let mut bytes = Vec::with_capacity(44 + data_len as usize);
+    bytes.extend(b"RIFF");
+    bytes.extend((36 + data_len).to_le_bytes());
+    bytes.extend(b"WAVEfmt ");
+    bytes.extend(16u32.to_le_bytes());
+    bytes.extend(1u16.to_le_bytes());
+    bytes.extend(2u16.to_le_bytes());
+    bytes.extend(rate.to_le_bytes());
+    bytes.extend((rate * 4).to_le_bytes());
+    bytes.extend(4u16.to_le_bytes());
+    bytes.extend(16u16.to_le_bytes());
+    bytes.extend(b"data");
+    bytes.extend(data_len.to_le_bytes());
+    for i in 0..frames {
+        let t = i as f64 / rate as f64;
+        let s = ((t * 440.0 * std::f64::consts::TAU).sin() * 8000.0) as i16;
+        bytes.extend(s.to_le_bytes());
+        bytes.extend(s.to_le_bytes());
+    }
+    fs::write(path, bytes).unwrap();
+}
+
+/// Sample rate and number of frames of a WAV file written by the WAV output
+fn read_wav_info(path: &Path) -> (u32, u32) {
+    let bytes = fs::read(path).unwrap();
+    let channels = u16::from_le_bytes([bytes[22], bytes[23]]) as u32;
+    let rate = u32::from_le_bytes(bytes[24..28].try_into().unwrap());
+    let data_len = u32::from_le_bytes(bytes[40..44].try_into().unwrap());
+    assert_eq!(bytes.len() as u32, 44 + data_len);
+    (rate, data_len / (channels * 4))
+}
+
+fn temp_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tori-player-{}-{}", test, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn wait_for(controller: &Controller, status: PlayerStatus) {
+    let start = Instant::now();
+    while controller.status() != status {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "timed out waiting for {:?}, the status is {:?}",
+            status,
+            controller.status()
+        );
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn assert_close(actual: u32, expected: u32, tolerance: u32) {
+    assert!(
+        actual.abs_diff(expected) <= tolerance,
+        "expected {} frames (± {}), got {}",
+        expected,
+        tolerance,
+        actual
+    );
+}
+
+#[test]
+fn test_null_output() {
+    let dir = temp_dir("null");
+    let track = dir.join("sine.wav");
+    write_sine(&track, RATE, 0.5);
+
+    let mut controller = Controller::default();
+    controller
+        .set_output(OutputKind::Null { realtime: true })
+        .unwrap();
+    let start = Instant::now();
+    controller.play(track.to_str().unwrap()).unwrap();
+    wait_for(&controller, PlayerStatus::Stopped);
+
+    // Plays in real time
+    assert!(start.elapsed() >= Duration::from_millis(400));
+    assert!(controller.take_error().is_none());
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn test_pause_while_idle() {
+    let dir = temp_dir("pause");
+    let track = dir.join("sine.wav");
+    write_sine(&track, RATE, 0.2);
+
+    let mut controller = Controller::default();
+    controller
+        .set_output(OutputKind::Null { realtime: false })
+        .unwrap();
+    controller.toggle_pause().unwrap();
+    assert!(controller.paused());
+
+    // The track starts paused
+    controller.play(track.to_str().unwrap()).unwrap();
+    wait_for(&controller, PlayerStatus::Paused);
+    assert!(controller.paused());
+    controller.toggle_pause().unwrap();
+    assert!(!controller.paused());
+    wait_for(&controller, PlayerStatus::Stopped);
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn test_wav_output_gapless() {
+    let dir = temp_dir("gapless");
+    let track = dir.join("sine.wav");
+    let out = dir.join("out.wav");
+    write_sine(&track, RATE, 1.0);
+
+    let mut controller = Controller::default();
+    controller
+        .set_output(OutputKind::Wav {
+            path: out.clone(),
+            sample_rate: None,
+        })
+        .unwrap();
+    controller.play(track.to_str().unwrap()).unwrap();
+    controller.queue(track.to_str().unwrap()).unwrap();
+    wait_for(&controller, PlayerStatus::Stopped);
+
+    let (rate, frames) = read_wav_info(&out);
+    assert_eq!(rate, RATE);
+    assert_eq!(frames, 2 * RATE);
+    assert!(controller.take_error().is_none());
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn test_wav_output_seek() {
+    let dir = temp_dir("seek");
+    let track = dir.join("sine.wav");
+    let out = dir.join("out.wav");
+    write_sine(&track, RATE, 1.0);
+
+    let mut controller = Controller::default();
+    controller
+        .set_output(OutputKind::Wav {
+            path: out.clone(),
+            sample_rate: None,
+        })
+        .unwrap();
+
+    // Start paused, so nothing is written before seeking
+    controller.toggle_pause().unwrap();
+    controller.play(track.to_str().unwrap()).unwrap();
+    wait_for(&controller, PlayerStatus::Paused);
+    controller.seek_absolute(50.0).unwrap();
+    controller.toggle_pause().unwrap();
+    wait_for(&controller, PlayerStatus::Stopped);
+
+    let (_, frames) = read_wav_info(&out);
+    assert_close(frames, RATE / 2, 1152);
+    assert!(controller.take_error().is_none());
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn test_wav_output_resample() {
+    let dir = temp_dir("resample");
+    let track = dir.join("sine.wav");
+    let out = dir.join("out.wav");
+    write_sine(&track, RATE, 1.0);
+
+    let mut controller = Controller::default();
+    controller
+        .set_output(OutputKind::Wav {
+            path: out.clone(),
+            sample_rate: Some(48000),
+        })
+        .unwrap();
+    controller.play(track.to_str().unwrap()).unwrap();
+    wait_for(&controller, PlayerStatus::Stopped);
+
+    // The resampler works in chunks, and the last one is padded with silence
+    let (rate, frames) = read_wav_info(&out);
+    assert_eq!(rate, 48000);
+    assert_close(frames, 48000, 4096);
+    assert!(controller.take_error().is_none());
+    fs::remove_dir_all(dir).ok();
+}
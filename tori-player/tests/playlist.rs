@@ -0,0 +1,73 @@
+//! Editing the queue while a track plays has to keep the position on that track
+
+use tori_player::controller::playlist::{Playlist, Track};
+
+fn playlist(paths: &[&str], position: Option<usize>) -> Playlist {
+    Playlist {
+        tracks: paths.iter().map(|path| Track::new(path)).collect(),
+        position,
+        ..Default::default()
+    }
+}
+
+fn paths(playlist: &Playlist) -> Vec<&str> {
+    playlist.tracks.iter().map(|t| t.path.as_str()).collect()
+}
+
+#[test]
+fn test_remove() {
+    let mut p = playlist(&["a", "b", "c", "d"], Some(2));
+    p.remove(0);
+    assert_eq!(paths(&p), ["b", "c", "d"]);
+    assert_eq!(p.current().unwrap().path, "c");
+
+    p.remove(2);
+    assert_eq!(p.current().unwrap().path, "c");
+
+    // The next track becomes the current one
+    p.remove(1);
+    assert_eq!(paths(&p), ["b"]);
+    assert_eq!(p.position, None);
+
+    let mut p = playlist(&["a", "b", "c"], Some(1));
+    p.remove(1);
+    assert_eq!(p.current().unwrap().path, "c");
+}
+
+#[test]
+fn test_move_track() {
+    let mut p = playlist(&["a", "b", "c", "d"], Some(1));
+    p.move_track(1, 3);
+    assert_eq!(paths(&p), ["a", "c", "d", "b"]);
+    assert_eq!(p.position, Some(3));
+
+    p.move_track(0, 2);
+    assert_eq!(paths(&p), ["c", "d", "a", "b"]);
+    assert_eq!(p.current().unwrap().path, "b");
+
+    p.move_track(3, 0);
+    assert_eq!(paths(&p), ["b", "c", "d", "a"]);
+    assert_eq!(p.position, Some(0));
+
+    p.move_track(3, 1);
+    assert_eq!(paths(&p), ["b", "a", "c", "d"]);
+    assert_eq!(p.position, Some(0));
+}
+
+#[test]
+fn test_loop_playlist() {
+    let mut p = playlist(&["a", "b"], Some(1));
+    assert!(p.peek_next().is_none());
+    assert_eq!(p.previous_position(), Some(0));
+
+    p.loop_playlist = true;
+    assert_eq!(p.peek_next().unwrap().path, "a");
+    assert!(p.advance());
+    assert_eq!(p.position, Some(0));
+    assert_eq!(p.previous_position(), Some(1));
+
+    p.loop_playlist = false;
+    assert!(p.advance());
+    assert!(!p.advance());
+    assert_eq!(p.position, None);
+}
@@ -1,11 +1,13 @@
-use crate::{command, error::Result, events, player::Player, rect_ops::RectOps};
+use crate::{
+    command, error::Result, events, m3u::playlist_management, player::{order, Player}, rect_ops::RectOps,
+};
 
 mod now_playing;
 use now_playing::NowPlaying;
 use tui::layout::Rect;
 
 use super::{
-    browse_screen::BrowseScreen,
+    browse_screen::{BrowseScreen, BrowseSession},
     component::{Component, MouseHandler},
     playlist_screen::PlaylistScreen,
     App, Mode,
@@ -40,6 +42,14 @@ impl<'a> AppScreen<'a> {
         self.selected = selection;
     }
 
+    pub fn browse_session(&self) -> BrowseSession {
+        self.browse.session()
+    }
+
+    pub fn restore_browse_session(&mut self, session: BrowseSession) -> Result<()> {
+        self.browse.restore_session(session)
+    }
+
     pub fn pass_event_down(&mut self, app: &mut App, event: events::Event) -> Result<()> {
         match self.selected {
             Selected::Browse => self.browse.handle_event(app, event),
@@ -53,12 +63,14 @@ impl<'a> AppScreen<'a> {
             Quit => {
                 app.quit();
             }
-            SeekForward => {
-                app.player.seek(10.)?;
+            SeekForward => self.handle_command(app, Seek(10.))?,
+            SeekBackward => self.handle_command(app, Seek(-10.))?,
+            Seek(seconds) => {
+                app.player.seek(seconds)?;
                 self.now_playing.update(&app.player);
             }
-            SeekBackward => {
-                app.player.seek(-10.)?;
+            SeekPercent(percent) => {
+                app.player.seek_absolute(percent)?;
                 self.now_playing.update(&app.player);
             }
             NextSong => {
@@ -78,15 +90,28 @@ impl<'a> AppScreen<'a> {
                 self.now_playing.update(&app.player);
             }
             ToggleLoop => {
-                app.player.toggle_loop_file()?;
+                let repeat = app.player.repeat()?.next();
+                app.player.set_repeat(repeat)?;
+                self.now_playing.update(&app.player);
+            }
+            Shuffle => {
+                app.shuffle.toggle(&mut app.player)?;
+                self.playlist.update(&app.player)?;
+            }
+            VolumeUp => self.handle_command(app, AddVolume(5))?,
+            VolumeDown => self.handle_command(app, AddVolume(-5))?,
+            AddVolume(x) => {
+                app.player.add_volume(x)?;
                 self.now_playing.update(&app.player);
             }
-            VolumeUp => {
-                app.player.add_volume(5)?;
+            SetVolume(x) => {
+                app.player.set_volume(x)?;
                 self.now_playing.update(&app.player);
             }
-            VolumeDown => {
-                app.player.add_volume(-5)?;
+            PlayPlaylist(Some(playlist)) => {
+                let count = playlist_management::play_playlist(&mut app.player, &playlist)?;
+                app.shuffle = order::Shuffle::default();
+                app.notify_ok(format!("Playing {} song(s) from '{}'", count, playlist));
                 self.now_playing.update(&app.player);
             }
             Mute => {
@@ -129,7 +154,7 @@ impl<'a> Component for AppScreen<'a> {
         use crossterm::event::KeyCode;
         use events::Event::*;
         match &event {
-            Command(cmd) => self.handle_command(app, *cmd)?,
+            Command(cmd) => self.handle_command(app, cmd.clone())?,
             Terminal(crossterm::event::Event::Key(key_event)) => match key_event.code {
                 KeyCode::Char('1') if self.mode() == Mode::Normal => {
                     self.select(Selected::Browse);
@@ -144,8 +169,12 @@ impl<'a> Component for AppScreen<'a> {
                 self.now_playing.update(&app.player);
                 self.pass_event_down(app, event)?;
             }
+            // The playlists can be changed from the other screens too
+            ChangedPlaylist => self.browse.handle_event(app, event)?,
             _ => self.pass_event_down(app, event)?,
         }
+        // Shuffling is turned off by any screen that replaces the queue
+        self.now_playing.shuffle = app.shuffle.is_on();
         Ok(())
     }
 }
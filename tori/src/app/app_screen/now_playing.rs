@@ -13,7 +13,7 @@ use crate::{
     },
     error::Result,
     events,
-    player::Player,
+    player::{order::Repeat, Player},
     rect_ops::RectOps,
 };
 
@@ -33,7 +33,9 @@ pub struct NowPlaying {
     pub time_pos: i64,
     pub time_rem: i64,
     pub paused: bool,
-    pub loop_file: bool,
+    pub repeat: Repeat,
+    /// Whether the queue is shuffled. The player doesn't know, so it's set by whoever shuffles it.
+    pub shuffle: bool,
     pub volume: i64,
 }
 
@@ -44,7 +46,7 @@ impl NowPlaying {
         self.time_pos = player.time_pos().unwrap_or_default();
         self.time_rem = player.time_remaining().unwrap_or_default();
         self.paused = player.paused().unwrap_or_default();
-        self.loop_file = player.looping_file().unwrap_or_default();
+        self.repeat = player.repeat().unwrap_or_default();
 
         self.volume = if player.muted().unwrap_or(false) {
             0
@@ -133,9 +135,18 @@ impl Component for NowPlaying {
                 ));
             }
 
-            if self.loop_file {
+            let repeat = match self.repeat {
+                Repeat::Off => None,
+                Repeat::All => Some("[repeat] "),
+                Repeat::One => Some("[repeat one] "),
+            };
+            if let Some(repeat) = repeat {
+                parts.push(Span::styled(repeat, Style::default().fg(Color::DarkGray)));
+            }
+
+            if self.shuffle {
                 parts.push(Span::styled(
-                    "[looping] ",
+                    "[shuffle] ",
                     Style::default().fg(Color::DarkGray),
                 ));
             }
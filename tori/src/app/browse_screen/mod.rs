@@ -1,14 +1,20 @@
 use crate::{
     app::{component::Component, App, MyBackend},
     command,
+    config::Config,
     error::Result,
     events::Event,
-    m3u::playlist_management,
-    player::Player,
+    m3u::{
+        history::{self, Action, Change},
+        playlist_management::{self, Edit, ModifiedExternally},
+        writer::{self, Conflicts},
+    },
+    player::{order, Player},
     rect_ops::RectOps,
 };
 
 use crossterm::event::{KeyCode, MouseEvent, MouseEventKind};
+use serde::{Deserialize, Serialize};
 
 use std::borrow::Cow;
 use std::rc::Rc;
@@ -24,11 +30,11 @@ mod playlists;
 use playlists::PlaylistsPane;
 
 mod songs;
-use songs::SongsPane;
+use songs::{SongsPane, SortingMethod};
 
 use super::Mode;
 use super::{component::MouseHandler, modal::HotkeyModal};
-use crate::app::modal::{self, ConfirmationModal, HelpModal, InputModal, Modal};
+use crate::app::modal::{self, ConfirmationModal, HelpModal, InputModal, Modal, SelectModal};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum ModalType {
@@ -39,9 +45,40 @@ enum ModalType {
     AddPlaylist,
     DeletePlaylist { playlist: String },
     RenameSong { playlist: String, index: usize },
-    DeleteSong { playlist: String, index: usize },
+    /// Renames every song in `indices` with a template, where {} is the current title
+    RenameSongs { playlist: String, indices: Vec<usize> },
+    DeleteSongs { playlist: String, indices: Vec<usize> },
+    MoveSong { playlist: String, index: usize },
+    AudioDevice,
+    OverwritePlaylist { playlist: String, action: Action },
+    RenamePlaylist { playlist: String },
+    DuplicatePlaylist { playlist: String },
+    MergePlaylist { playlist: String },
+    TransferSongs {
+        playlist: String,
+        indices: Vec<usize>,
+        transfer: Transfer,
+    },
+    /// Some of the songs are already in `target`
+    TransferDuplicates {
+        playlist: String,
+        target: String,
+        indices: Vec<usize>,
+        duplicates: Vec<usize>,
+        transfer: Transfer,
+    },
 }
 
+/// Whether songs are copied or moved to another playlist
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transfer {
+    Copy,
+    Move,
+}
+
+const SKIP_DUPLICATES: &str = "Skip them";
+const ADD_DUPLICATES: &str = "Add them anyway";
+
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 #[repr(i8)]
 enum BrowsePane {
@@ -51,6 +88,15 @@ enum BrowsePane {
     Modal(ModalType),
 }
 
+/// What the browse screen was showing, so it can be restored in the next session
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BrowseSession {
+    pub playlist: Option<String>,
+    pub sorting_method: SortingMethod,
+    pub filter: String,
+}
+
 #[derive(Default)]
 pub struct BrowseScreen<'a> {
     playlists: PlaylistsPane,
@@ -85,12 +131,37 @@ impl<'a> BrowseScreen<'a> {
         self.songs.update_from_playlist_pane(&self.playlists)
     }
 
+    pub fn session(&self) -> BrowseSession {
+        BrowseSession {
+            playlist: self.playlists.selected_item().map(String::from),
+            sorting_method: self.songs.sorting_method(),
+            filter: self.songs.filter().to_string(),
+        }
+    }
+
+    /// Shows what was shown in a previous session. The filter is only restored if the playlist
+    /// still exists.
+    pub fn restore_session(&mut self, session: BrowseSession) -> Result<()> {
+        let mut filter = String::new();
+        if let Some(playlist) = &session.playlist {
+            if self.playlists.select_named_quietly(playlist) {
+                self.reload_songs()?;
+                filter = session.filter;
+            }
+        }
+        self.songs.restore_view(session.sorting_method, filter);
+        Ok(())
+    }
+
     /// Passes the event down to the currently selected pane.
     fn pass_event_down(&mut self, app: &mut App, event: Event) -> Result<()> {
         use BrowsePane::*;
         match self.selected_pane {
             Playlists => self.playlists.handle_event(app, event),
-            Songs => self.songs.handle_event(app, event),
+            Songs => self
+                .songs
+                .handle_event(app, event)
+                .or_else(|e| self.ask_to_overwrite(e)),
             Modal(_) => {
                 let msg = self.modal.handle_event(event)?;
                 self.handle_modal_message(app, msg)
@@ -137,10 +208,8 @@ impl<'a> BrowseScreen<'a> {
                         Err(CreatePlaylistError::PlaylistAlreadyExists) => {
                             app.notify_err(format!("Playlist '{}' already exists!", playlist));
                         }
-                        Err(CreatePlaylistError::InvalidChar(c)) => {
-                            app.notify_err(format!("Playlist names cannot contain '{}'", c));
-                        }
                         Err(CreatePlaylistError::IOError(e)) => return Err(e.into()),
+                        Err(e) => app.notify_err(e.to_string()),
                     }
                     self.selected_pane = BrowsePane::Playlists;
                 }
@@ -150,10 +219,11 @@ impl<'a> BrowseScreen<'a> {
                     self.selected_pane = BrowsePane::Playlists;
                 }
                 (DeletePlaylist { playlist }, Commit(_)) => {
-                    playlist_management::delete_playlist(playlist)?;
-                    self.playlists = PlaylistsPane::new()?;
-                    self.reload_songs()?;
+                    let change = Change::Trash {
+                        playlist: playlist.clone(),
+                    };
                     self.selected_pane = BrowsePane::Playlists;
+                    self.run_history(app, Action::Perform(change), Conflicts::Check)?;
                 }
 
                 // Play
@@ -162,6 +232,7 @@ impl<'a> BrowseScreen<'a> {
                 }
                 (Play, Commit(path)) => {
                     app.player.play(&path)?;
+                    app.shuffle = order::Shuffle::default();
                     self.selected_pane = BrowsePane::Songs;
                 }
 
@@ -176,14 +247,77 @@ impl<'a> BrowseScreen<'a> {
                     self.selected_pane = BrowsePane::Songs;
                 }
                 (RenameSong { playlist, index }, Commit(new_name)) => {
-                    playlist_management::rename_song(playlist, *index, &new_name)?;
-                    self.reload_songs()?;
+                    let change = Change::Edit {
+                        playlist: playlist.clone(),
+                        edit: Edit::Rename {
+                            index: *index,
+                            title: new_name,
+                        },
+                    };
+                    self.selected_pane = BrowsePane::Songs;
+                    self.run_history(app, Action::Perform(change), Conflicts::Check)?;
+                }
+
+                // RenameSongs
+                (
+                    RenameSongs {
+                        playlist: _,
+                        indices: _,
+                    },
+                    Quit,
+                ) => {
+                    self.selected_pane = BrowsePane::Songs;
+                }
+                (RenameSongs { playlist, indices }, Commit(template)) => {
+                    let songs = self.songs.songs();
+                    let edits = indices
+                        .iter()
+                        .map(|&index| Edit::Rename {
+                            index,
+                            title: template.replace("{}", &songs[index].title),
+                        })
+                        .collect();
+                    let change = Change::Edit {
+                        playlist: playlist.clone(),
+                        edit: Edit::Batch { edits },
+                    };
+                    self.selected_pane = BrowsePane::Songs;
+                    self.run_history(app, Action::Perform(change), Conflicts::Check)?;
+                }
+
+                // DeleteSongs
+                (
+                    DeleteSongs {
+                        playlist: _,
+                        indices: _,
+                    },
+                    Quit,
+                ) => {
+                    self.selected_pane = BrowsePane::Songs;
+                }
+                (DeleteSongs { playlist, indices }, Commit(_)) => {
+                    let edit = match indices[..] {
+                        [index] => Edit::Delete { index },
+                        // Delete from the bottom up, so the indices don't shift
+                        _ => Edit::Batch {
+                            edits: indices
+                                .iter()
+                                .rev()
+                                .map(|&index| Edit::Delete { index })
+                                .collect(),
+                        },
+                    };
+                    let change = Change::Edit {
+                        playlist: playlist.clone(),
+                        edit,
+                    };
                     self.selected_pane = BrowsePane::Songs;
+                    self.run_history(app, Action::Perform(change), Conflicts::Check)?;
                 }
 
-                // DeleteSong
+                // MoveSong
                 (
-                    DeleteSong {
+                    MoveSong {
                         playlist: _,
                         index: _,
                     },
@@ -191,10 +325,158 @@ impl<'a> BrowseScreen<'a> {
                 ) => {
                     self.selected_pane = BrowsePane::Songs;
                 }
-                (DeleteSong { playlist, index }, Commit(_)) => {
-                    playlist_management::delete_song(playlist, *index)?;
+                (MoveSong { playlist: _, index }, Commit(position)) => {
+                    let index = *index;
+                    self.selected_pane = BrowsePane::Songs;
+                    match position.trim().parse::<usize>() {
+                        Ok(position) => {
+                            let len = self.songs.songs().len();
+                            let to = position.clamp(1, len.max(1)) - 1;
+                            self.songs
+                                .move_song(index, to)
+                                .or_else(|e| self.ask_to_overwrite(e))?;
+                        }
+                        Err(_) => app.notify_err(format!("'{}' isn't a position", position)),
+                    }
+                }
+
+                // AudioDevice
+                (AudioDevice, Quit) => {
+                    self.selected_pane = BrowsePane::Songs;
+                }
+                (AudioDevice, Commit(device)) => {
+                    self.selected_pane = BrowsePane::Songs;
+                    app.player.set_output_device(&device)?;
+                    app.notify_ok(format!("Playing on {}", device));
+                }
+
+                // RenamePlaylist
+                (RenamePlaylist { playlist: _ }, Quit) => {
+                    self.selected_pane = BrowsePane::Playlists;
+                }
+                (RenamePlaylist { playlist }, Commit(new_name)) => {
+                    let change = Change::RenamePlaylist {
+                        from: playlist.clone(),
+                        to: new_name.clone(),
+                    };
+                    let unchanged = *playlist == new_name;
+                    self.selected_pane = BrowsePane::Playlists;
+                    if !unchanged
+                        && self.run_history(app, Action::Perform(change), Conflicts::Check)?
+                    {
+                        self.playlists.select_named(app, &new_name);
+                    }
+                }
+
+                // DuplicatePlaylist
+                (DuplicatePlaylist { playlist: _ }, Quit) => {
+                    self.selected_pane = BrowsePane::Playlists;
+                }
+                (DuplicatePlaylist { playlist }, Commit(copy)) => {
+                    let change = Change::CopyPlaylist {
+                        playlist: playlist.clone(),
+                        copy: copy.clone(),
+                    };
+                    self.selected_pane = BrowsePane::Playlists;
+                    if self.run_history(app, Action::Perform(change), Conflicts::Check)? {
+                        self.playlists.select_named(app, &copy);
+                    }
+                }
+
+                // MergePlaylist
+                (MergePlaylist { playlist: _ }, Quit) => {
+                    self.selected_pane = BrowsePane::Playlists;
+                }
+                (MergePlaylist { playlist }, Commit(target)) => {
+                    let message = format!("Added the songs of '{}' to '{}'", playlist, target);
+                    let change = Change::Edit {
+                        playlist: target,
+                        edit: Edit::Append {
+                            text: playlist_management::playlist_text(playlist)?,
+                        },
+                    };
+                    self.selected_pane = BrowsePane::Playlists;
+                    if self.run_history(app, Action::Perform(change), Conflicts::Check)? {
+                        app.notify_ok(message);
+                    }
+                }
+
+                // TransferSongs
+                (TransferSongs { .. }, Quit) => {
+                    self.selected_pane = BrowsePane::Songs;
+                }
+                (
+                    TransferSongs {
+                        playlist,
+                        indices,
+                        transfer,
+                    },
+                    Commit(target),
+                ) => {
+                    let (playlist, indices, transfer) = (playlist.clone(), indices.clone(), *transfer);
+                    let in_target = playlist_management::song_paths(&target)?;
+                    let songs = self.songs.songs();
+                    let duplicates: Vec<_> = indices
+                        .iter()
+                        .copied()
+                        .filter(|&i| in_target.contains(&songs[i].path))
+                        .collect();
+
+                    self.selected_pane = BrowsePane::Songs;
+                    if duplicates.is_empty() {
+                        self.transfer_songs(app, &playlist, &target, &indices, &[], transfer)?;
+                    } else {
+                        let title = format!(
+                            " {} of these songs are already in '{}' ",
+                            duplicates.len(),
+                            target
+                        );
+                        let options = vec![SKIP_DUPLICATES.into(), ADD_DUPLICATES.into()];
+                        self.selected_pane = BrowsePane::Modal(ModalType::TransferDuplicates {
+                            playlist,
+                            target,
+                            indices,
+                            duplicates,
+                            transfer,
+                        });
+                        self.modal = Box::new(SelectModal::new(title, options));
+                    }
+                }
+
+                // TransferDuplicates
+                (TransferDuplicates { .. }, Quit) => {
+                    self.selected_pane = BrowsePane::Songs;
+                }
+                (
+                    TransferDuplicates {
+                        playlist,
+                        target,
+                        indices,
+                        duplicates,
+                        transfer,
+                    },
+                    Commit(choice),
+                ) => {
+                    let (playlist, target, indices, transfer) =
+                        (playlist.clone(), target.clone(), indices.clone(), *transfer);
+                    let skipped = match choice.as_str() {
+                        SKIP_DUPLICATES => duplicates.clone(),
+                        _ => Vec::new(),
+                    };
+                    self.selected_pane = BrowsePane::Songs;
+                    self.transfer_songs(app, &playlist, &target, &indices, &skipped, transfer)?;
+                }
+
+                // OverwritePlaylist
+                (OverwritePlaylist { playlist, action: _ }, Quit) => {
+                    app.notify_info(format!("Reloaded '{}'", playlist));
+                    self.selected_pane = BrowsePane::Songs;
                     self.reload_songs()?;
+                }
+                (OverwritePlaylist { playlist: _, action }, Commit(_)) => {
+                    let action = action.clone();
                     self.selected_pane = BrowsePane::Songs;
+                    self.run_history(app, action, Conflicts::Overwrite)?;
                 }
             }
         } else {
@@ -216,6 +498,15 @@ impl<'a> BrowseScreen<'a> {
             OpenHotkeyModal => {
                 self.open_hotkey_modal();
             }
+            SelectAudioDevice => {
+                let devices = app.player.output_devices()?;
+                if devices.is_empty() {
+                    app.notify_err("No audio output devices found");
+                } else {
+                    self.selected_pane = BrowsePane::Modal(ModalType::AudioDevice);
+                    self.modal = Box::new(SelectModal::new(" Audio device ", devices));
+                }
+            }
             SelectRight | SelectLeft => self.select_next_panel(),
             // TODO: this should probably be in each pane's handle_event, somehow
             Add => match self.selected_pane {
@@ -237,9 +528,32 @@ impl<'a> BrowseScreen<'a> {
                 BrowsePane::Modal(_) => {}
             },
             Rename => match self.selected_pane {
-                BrowsePane::Playlists => {}
+                BrowsePane::Playlists => {
+                    if let Some(playlist) = self.playlists.selected_item() {
+                        let modal = InputModal::new(" Rename playlist (esc cancels) ")
+                            .set_input(playlist.to_owned());
+                        self.selected_pane = BrowsePane::Modal(ModalType::RenamePlaylist {
+                            playlist: playlist.to_owned(),
+                        });
+                        self.modal = Box::new(modal);
+                    }
+                }
                 BrowsePane::Songs => {
-                    if let (Some(playlist), Some(index)) =
+                    let indices = self.songs.selection();
+                    if let (Some(playlist), [_, _, ..]) =
+                        (self.playlists.selected_item(), &indices[..])
+                    {
+                        let title = format!(
+                            " Rename {} songs, {{}} is the current title (esc cancels) ",
+                            indices.len()
+                        );
+                        let modal = InputModal::new(title).set_input("{}".into());
+                        self.selected_pane = BrowsePane::Modal(ModalType::RenameSongs {
+                            playlist: playlist.to_owned(),
+                            indices,
+                        });
+                        self.modal = Box::new(modal);
+                    } else if let (Some(playlist), Some(index)) =
                         (self.playlists.selected_item(), self.songs.selected_index())
                     {
                         // kind of a hack, sorry
@@ -272,16 +586,20 @@ impl<'a> BrowseScreen<'a> {
                     }
                 }
                 BrowsePane::Songs => {
-                    if let (Some(playlist), Some(index)) =
-                        (self.playlists.selected_item(), self.songs.selected_index())
+                    let indices = self.songs.selection();
+                    if let (Some(playlist), false) =
+                        (self.playlists.selected_item(), indices.is_empty())
                     {
-                        let title = format!(
-                            "Do you really want to delete '{}'?",
-                            self.songs.selected_item().unwrap().title
-                        );
-                        let modal_type = ModalType::DeleteSong {
+                        let title = match indices[..] {
+                            [index] => format!(
+                                "Do you really want to delete '{}'?",
+                                self.songs.songs()[index].title
+                            ),
+                            _ => format!("Do you really want to delete {} songs?", indices.len()),
+                        };
+                        let modal_type = ModalType::DeleteSongs {
                             playlist: playlist.to_owned(),
-                            index,
+                            indices,
                         };
                         self.open_confirmation(title.as_str(), modal_type)
                             .apply_style(Style::default().fg(Color::LightRed));
@@ -290,6 +608,76 @@ impl<'a> BrowseScreen<'a> {
                 _ => {}
             },
             OpenInEditor => self.playlists.open_editor_for_selected(app)?,
+            MoveSongTo => {
+                if let (Some(playlist), Some(index)) =
+                    (self.playlists.selected_item(), self.songs.selected_index())
+                {
+                    let title = format!(
+                        " Move to position (1-{}, esc cancels) ",
+                        self.songs.songs().len()
+                    );
+                    let modal = InputModal::new(title).set_input((index + 1).to_string());
+                    self.selected_pane = BrowsePane::Modal(ModalType::MoveSong {
+                        playlist: playlist.to_owned(),
+                        index,
+                    });
+                    self.modal = Box::new(modal);
+                }
+            }
+            DuplicatePlaylist => {
+                if let Some(playlist) = self.playlists.selected_item() {
+                    let modal = InputModal::new(" Duplicate playlist (esc cancels) ")
+                        .set_input(format!("{} (copy)", playlist));
+                    self.selected_pane = BrowsePane::Modal(ModalType::DuplicatePlaylist {
+                        playlist: playlist.to_owned(),
+                    });
+                    self.modal = Box::new(modal);
+                }
+            }
+            MergePlaylist => {
+                if let Some(playlist) = self.playlists.selected_item() {
+                    let others: Vec<_> = self
+                        .playlists
+                        .playlists()
+                        .iter()
+                        .filter(|p| *p != playlist)
+                        .cloned()
+                        .collect();
+                    if others.is_empty() {
+                        app.notify_err("There are no other playlists to merge into");
+                    } else {
+                        let title = format!(" Add the songs of '{}' to ", playlist);
+                        self.selected_pane = BrowsePane::Modal(ModalType::MergePlaylist {
+                            playlist: playlist.to_owned(),
+                        });
+                        self.modal = Box::new(SelectModal::new(title, others));
+                    }
+                }
+            }
+            CopyToPlaylist | MoveToPlaylist | CopyShownToPlaylist | MoveShownToPlaylist => {
+                let indices = match cmd {
+                    CopyToPlaylist | MoveToPlaylist => self.songs.selection(),
+                    _ => self.songs.shown_indices().to_vec(),
+                };
+                let transfer = match cmd {
+                    CopyToPlaylist | CopyShownToPlaylist => Transfer::Copy,
+                    _ => Transfer::Move,
+                };
+                self.open_transfer_modal(app, indices, transfer);
+            }
+            PlayPlaylist(None) => {
+                if let Some(playlist) = self.playlists.selected_item() {
+                    let count = playlist_management::play_playlist(&mut app.player, playlist)?;
+                    app.shuffle = order::Shuffle::default();
+                    app.notify_ok(format!("Playing {} song(s) from '{}'", count, playlist));
+                }
+            }
+            Undo => {
+                self.run_history(app, Action::Undo, Conflicts::Check)?;
+            }
+            Redo => {
+                self.run_history(app, Action::Redo, Conflicts::Check)?;
+            }
             _ => self.pass_event_down(app, Event::Command(cmd))?,
         }
         Ok(())
@@ -328,6 +716,144 @@ impl<'a> BrowseScreen<'a> {
         &mut self.modal
     }
 
+    /// Performs, undoes or redoes a change to the playlists, and reloads them. Returns false if
+    /// it wasn't done because a playlist was changed outside of tori.
+    fn run_history(&mut self, app: &mut App, action: Action, conflicts: Conflicts) -> Result<bool> {
+        let res = history::run(&action, conflicts);
+        self.playlists.reload_from_dir()?;
+        self.reload_songs()?;
+        match (res, action) {
+            (Ok(Some(change)), Action::Undo) => app.notify_info(format!("Undid {}", change)),
+            (Ok(Some(change)), Action::Redo) => app.notify_info(format!("Redid {}", change)),
+            (Ok(None), Action::Undo) => app.notify_info("Nothing to undo"),
+            (Ok(None), Action::Redo) => app.notify_info("Nothing to redo"),
+            (Ok(_), Action::Perform(_)) => {}
+            (Err(e), _) => {
+                self.ask_to_overwrite(e)?;
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Asks which playlist to copy or move the songs at `indices` of the selected playlist to
+    fn open_transfer_modal(&mut self, app: &mut App, indices: Vec<usize>, transfer: Transfer) {
+        let playlist = match self.playlists.selected_item() {
+            Some(playlist) if !indices.is_empty() => playlist.to_owned(),
+            _ => return,
+        };
+        let others: Vec<_> = self
+            .playlists
+            .playlists()
+            .iter()
+            .filter(|&p| *p != playlist)
+            .cloned()
+            .collect();
+        if others.is_empty() {
+            app.notify_err("There are no other playlists");
+            return;
+        }
+
+        let verb = match transfer {
+            Transfer::Copy => "Copy",
+            Transfer::Move => "Move",
+        };
+        let title = match indices[..] {
+            [i] => format!(" {} '{}' to ", verb, self.songs.songs()[i].title),
+            _ => format!(" {} {} songs to ", verb, indices.len()),
+        };
+        self.selected_pane = BrowsePane::Modal(ModalType::TransferSongs {
+            playlist,
+            indices,
+            transfer,
+        });
+        self.modal = Box::new(SelectModal::new(title, others));
+    }
+
+    /// Copies or moves the songs at `indices` of `playlist` to the end of `target`, except the ones
+    /// in `skipped`. When moving, the skipped songs are still removed from `playlist`.
+    fn transfer_songs(
+        &mut self,
+        app: &mut App,
+        playlist: &str,
+        target: &str,
+        indices: &[usize],
+        skipped: &[usize],
+        transfer: Transfer,
+    ) -> Result<()> {
+        // The indices are of the songs that were shown, which may not be in the file anymore
+        if !writer::is_unchanged(&Config::playlist_path(playlist))? {
+            self.reload_songs()?;
+            app.notify_err(format!(
+                "'{}' was changed outside of tori, so nothing was transferred. Check the songs and try again",
+                playlist
+            ));
+            return Ok(());
+        }
+
+        let added: Vec<_> = indices
+            .iter()
+            .copied()
+            .filter(|i| !skipped.contains(i))
+            .collect();
+
+        let mut changes = Vec::new();
+        if !added.is_empty() {
+            changes.push(Change::Edit {
+                playlist: target.to_string(),
+                edit: Edit::Append {
+                    text: playlist_management::songs_text(playlist, &added)?,
+                },
+            });
+        }
+        if transfer == Transfer::Move {
+            // Delete from the bottom up, so the indices don't shift
+            let mut removed = indices.to_vec();
+            removed.sort_unstable_by(|a, b| b.cmp(a));
+            changes.push(Change::Edit {
+                playlist: playlist.to_string(),
+                edit: Edit::Batch {
+                    edits: removed
+                        .into_iter()
+                        .map(|index| Edit::Delete { index })
+                        .collect(),
+                },
+            });
+        }
+
+        let change = match changes.len() {
+            0 => {
+                app.notify_info(format!("All of these songs are already in '{}'", target));
+                return Ok(());
+            }
+            1 => changes.remove(0),
+            _ => Change::Batch { changes },
+        };
+        if self.run_history(app, Action::Perform(change), Conflicts::Check)? {
+            let verb = match transfer {
+                Transfer::Copy => "Copied",
+                Transfer::Move => "Moved",
+            };
+            app.notify_ok(format!("{} {} song(s) to '{}'", verb, added.len(), target));
+        }
+        Ok(())
+    }
+
+    /// If an edit failed because the playlist was changed outside of tori, asks whether to
+    /// overwrite it anyway. Other errors are returned as they are.
+    fn ask_to_overwrite(&mut self, err: crate::error::Error) -> Result<()> {
+        let conflict = err.downcast::<ModifiedExternally>()?;
+        let title = format!("{}. Overwrite it anyway?", conflict);
+        let ModifiedExternally { playlist, retry } = *conflict;
+        let modal_type = ModalType::OverwritePlaylist {
+            playlist,
+            action: retry,
+        };
+        self.open_confirmation(&title, modal_type)
+            .apply_style(Style::default().fg(Color::LightRed));
+        Ok(())
+    }
+
     fn open_confirmation(&mut self, title: &str, modal_type: ModalType) -> &mut Box<dyn Modal> {
         self.selected_pane = BrowsePane::Modal(modal_type);
         self.modal = Box::new(ConfirmationModal::new(title));
@@ -397,8 +923,25 @@ impl<'t> Component for BrowseScreen<'t> {
                 app.notify_ok(format!("\"{}\" was added to {}", song, playlist));
             }
             SecondTick => {}
+            // Shown by the app
+            Error(_) => {}
             ChangedPlaylist => {
+                // Playlists may have been created outside of this screen, like by SaveQueue
+                let selected = self.playlists.selected_item().map(str::to_owned);
+                self.playlists.reload_from_dir()?;
+                if let Some(name) = selected {
+                    if self.playlists.selected_item() != Some(name.as_str()) {
+                        self.playlists.select_named(app, &name);
+                    }
+                }
                 self.reload_songs()?;
+                if let Some(warning) = self.songs.warnings().first() {
+                    let count = self.songs.warnings().len();
+                    app.notify_info(format!(
+                        "{} line(s) in this playlist couldn't be fully understood. The first one is at {}",
+                        count, warning
+                    ));
+                }
             }
             Terminal(event) => self.handle_terminal_event(app, event)?,
         }
@@ -432,7 +975,9 @@ impl<'a> MouseHandler for BrowseScreen<'a> {
             if let MouseEventKind::Down(_) = event.kind {
                 self.selected_pane = BrowsePane::Songs;
             }
-            self.songs.handle_mouse(app, hchunks[1], event)
+            self.songs
+                .handle_mouse(app, hchunks[1], event)
+                .or_else(|e| self.ask_to_overwrite(e))
         }
     }
 }
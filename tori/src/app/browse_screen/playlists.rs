@@ -8,12 +8,12 @@ use crate::{
     config::Config,
     error::Result,
     events::Event,
+    m3u::playlist_management,
 };
 use crossterm::event::{KeyCode, KeyEvent, MouseButton, MouseEventKind};
 use crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
 use crossterm::ExecutableCommand;
 use std::io;
-use std::result::Result as StdResult;
 use tui::{
     layout::{self, Rect},
     style::{Color, Style},
@@ -36,26 +36,7 @@ impl PlaylistsPane {
     }
 
     pub fn reload_from_dir(&mut self) -> Result<()> {
-        let dir = std::fs::read_dir(&Config::global().playlists_dir)
-            .map_err(|e| format!("Failed to read playlists directory: {}", e))?;
-
-        use std::fs::DirEntry;
-        let extract_playlist_name = |entry: StdResult<DirEntry, io::Error>| {
-            Ok(entry
-                .unwrap()
-                .file_name()
-                .into_string()
-                .map_err(|filename| format!("File '{:?}' has invalid UTF-8", filename))?
-                .trim_end_matches(".m3u8")
-                .to_string())
-        };
-
-        self.playlists = dir
-            .into_iter()
-            .map(extract_playlist_name)
-            .collect::<Result<_>>()?;
-
-        self.playlists.sort();
+        self.playlists = playlist_management::list_playlists()?;
         self.refresh_shown();
         Ok(())
     }
@@ -109,6 +90,32 @@ impl PlaylistsPane {
         app.channel.send(Event::ChangedPlaylist).unwrap();
     }
 
+    /// Selects the playlist called `name`, if it's shown
+    pub fn select_named(&mut self, app: &mut App, name: &str) {
+        if self.select_named_quietly(name) {
+            app.channel.send(Event::ChangedPlaylist).unwrap();
+        }
+    }
+
+    /// Like [select_named](Self::select_named), but the songs pane isn't told to reload. Returns
+    /// whether the playlist was found.
+    pub fn select_named_quietly(&mut self, name: &str) -> bool {
+        let index = self
+            .shown
+            .items
+            .iter()
+            .position(|&i| self.playlists[i] == name);
+        if index.is_some() {
+            self.shown.state.select(index);
+        }
+        index.is_some()
+    }
+
+    /// Every playlist, including the ones hidden by the filter
+    pub fn playlists(&self) -> &[String] {
+        &self.playlists
+    }
+
     pub fn selected_item(&self) -> Option<&str> {
         self.shown
             .selected_item()
@@ -132,6 +139,7 @@ impl PlaylistsPane {
 
             res?;
             self.reload_from_dir()?;
+            app.channel.sender.send(Event::ChangedPlaylist).unwrap();
             app.terminal.clear()?;
         }
         Ok(())
@@ -198,7 +206,7 @@ impl Component for PlaylistsPane {
                 .keybindings
                 .0
                 .iter()
-                .find(|&(_key, &cmd)| cmd == Command::Add)
+                .find(|(_key, cmd)| **cmd == Command::Add)
                 .map(|(key, _)| key.0.as_str())
                 .unwrap_or("a");
 
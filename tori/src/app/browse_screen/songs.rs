@@ -1,4 +1,5 @@
 use std::borrow::Cow;
+use std::collections::BTreeSet;
 
 use std::path::Path;
 
@@ -6,21 +7,31 @@ use crate::app::component::MouseHandler;
 use crate::command::Command;
 use crate::error::Result;
 use crate::events::Event;
-use crate::player::Player;
+use crate::player::{order, Player};
 use crate::util::ClickInfo;
 use crate::widgets::Scrollbar;
 use crate::{
     app::{component::Component, filtered_list::FilteredList, App, Mode, MyBackend},
     config::Config,
 };
-use crate::{m3u, util};
+use crate::{
+    m3u::{
+        self,
+        history::{self, Change},
+        playlist_management::Edit,
+        writer::{self, Conflicts},
+    },
+    util,
+};
+
+use serde::{Deserialize, Serialize};
 
-use crossterm::event::{KeyCode, KeyEvent, MouseButton, MouseEventKind};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEventKind};
 use tui::layout::Rect;
 use tui::widgets::{Paragraph, Wrap};
 use tui::{
     layout::{self, Constraint},
-    style::{Color, Style},
+    style::{Color, Modifier, Style},
     widgets::{Block, BorderType, Borders, Row, Table, TableState},
     Frame,
 };
@@ -28,8 +39,8 @@ use tui::{
 /////////////////////////////////
 //        SortingMethod        //
 /////////////////////////////////
-#[derive(Debug, Default, Clone, Copy)]
-enum SortingMethod {
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SortingMethod {
     #[default]
     /// identity permutation
     Index,
@@ -79,9 +90,22 @@ pub struct SongsPane<'t> {
     /// Generally the name of the playlist
     title: Cow<'t, str>,
     songs: Vec<m3u::Song>,
+    /// Problems found when parsing the playlist
+    warnings: Vec<m3u::parser::Warning>,
     shown: FilteredList<TableState>,
     sorting_method: SortingMethod,
     filter: String,
+    /// Songs marked for batch operations, as indices into `songs`
+    marked: BTreeSet<usize>,
+    /// Where visual mode started, as an index into `songs`. The songs shown between it and the
+    /// selected one are marked too.
+    visual_anchor: Option<usize>,
+    /// Whether visual mode was started by dragging the mouse, so it ends when the button is released
+    mouse_visual: bool,
+    /// The song under the mouse when the button was pressed
+    mouse_press_index: Option<usize>,
+    /// The song that's being dragged to another position
+    dragged_song: Option<usize>,
     last_click: Option<ClickInfo>,
     mouse_press_location: Option<MousePressLocation>,
 }
@@ -119,7 +143,7 @@ impl<'t> SongsPane<'t> {
     }
 
     pub fn update_from_playlist(&mut self, path: impl AsRef<Path>) -> Result<()> {
-        let file = std::fs::File::open(&path)
+        let content = std::fs::read_to_string(&path)
             .map_err(|_| format!("Couldn't open playlist file {}", path.as_ref().display()))?;
 
         let title = Cow::Owned(
@@ -130,13 +154,18 @@ impl<'t> SongsPane<'t> {
                 .to_string(),
         );
 
-        let songs = m3u::Parser::from_reader(file).all_songs()?;
+        let mut parser = m3u::Parser::from_string(&content);
+        let songs = parser.all_songs()?;
+        writer::remember(path.as_ref(), &content);
         let state = self.state();
 
         // Update stuff
         self.title = title;
         self.songs = songs;
+        self.warnings = parser.warnings().to_vec();
         self.filter.clear();
+        // The indices may point to other songs now
+        self.clear_marks();
         self.refresh_shown();
 
         // Try to reuse previous state
@@ -151,6 +180,41 @@ impl<'t> SongsPane<'t> {
         Ok(())
     }
 
+    /// Swaps the `index`-th song with the `index+1`-th in the playlist file
+    fn swap_in_file(&self, index: usize) -> Result<()> {
+        let change = Change::Edit {
+            playlist: self.title.to_string(),
+            edit: Edit::Swap { index },
+        };
+        history::perform(change, Conflicts::Check)
+    }
+
+    /// Moves the song at `from` so it ends up at index `to` of the playlist, in a single write,
+    /// and keeps it selected
+    pub fn move_song(&mut self, from: usize, to: usize) -> Result<()> {
+        if from == to || from >= self.songs.len() || to >= self.songs.len() {
+            return Ok(());
+        }
+        let change = Change::Edit {
+            playlist: self.title.to_string(),
+            edit: Edit::Move { from, to },
+        };
+        history::perform(change, Conflicts::Check)?;
+
+        let song = self.songs.remove(from);
+        self.songs.insert(to, song);
+        self.clear_marks();
+        self.refresh_shown();
+        if let Some(row) = self.shown.items.iter().position(|&i| i == to) {
+            self.select_index(Some(row));
+        }
+        Ok(())
+    }
+
+    pub fn warnings(&self) -> &[m3u::parser::Warning] {
+        &self.warnings
+    }
+
     fn refresh_shown(&mut self) {
         let pred = |s: &m3u::Song| {
             self.filter.is_empty()
@@ -169,6 +233,25 @@ impl<'t> SongsPane<'t> {
         self.sorting_method = self.sorting_method.next();
     }
 
+    pub fn sorting_method(&self) -> SortingMethod {
+        self.sorting_method
+    }
+
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+
+    /// Sorts and filters the songs like they were in a previous session
+    pub fn restore_view(&mut self, sorting_method: SortingMethod, mut filter: String) {
+        // A filter that was still being typed is applied, as if enter had been pressed
+        if !filter.is_empty() && !filter.ends_with('\n') {
+            filter.push('\n');
+        }
+        self.sorting_method = sorting_method;
+        self.filter = filter;
+        self.refresh_shown();
+    }
+
     #[allow(clippy::single_match)]
     fn handle_terminal_event(
         &mut self,
@@ -188,6 +271,7 @@ impl<'t> SongsPane<'t> {
                     Enter => self.play_selected(app)?,
                     Esc => {
                         self.filter.clear();
+                        self.clear_marks();
                         self.refresh_shown();
                     }
                     // Go to the top, kind of like in vim
@@ -220,9 +304,24 @@ impl<'t> SongsPane<'t> {
             SelectNext => self.select_next(),
             SelectPrev => self.select_prev(),
             QueueSong => {
-                if let Some(song) = self.selected_item() {
-                    app.player.queue(&song.path)?;
+                for i in self.selection() {
+                    app.player.queue(&self.songs[i].path)?;
                 }
+                self.clear_marks();
+            }
+            QueueNext => {
+                let selection = self.selection();
+                if app.player.playlist_position().is_ok() {
+                    // Inserted from the last one, so they end up in order
+                    for &i in selection.iter().rev() {
+                        app.player.play_next(&self.songs[i].path)?;
+                    }
+                } else {
+                    for &i in &selection {
+                        app.player.queue(&self.songs[i].path)?;
+                    }
+                }
+                self.clear_marks();
             }
             QueueShown => {
                 for &i in self.shown.items.iter() {
@@ -230,9 +329,6 @@ impl<'t> SongsPane<'t> {
                     app.player.queue(path)?;
                 }
             }
-            Shuffle => {
-                app.player.shuffle()?;
-            }
             OpenInBrowser => {
                 if let Some(song) = self.selected_item() {
                     // TODO: reconsider if I really need a library to write this one line
@@ -259,7 +355,7 @@ impl<'t> SongsPane<'t> {
             }
             SwapSongUp if self.filter.is_empty() => match self.selected_index() {
                 Some(i) if i >= 1 => {
-                    m3u::playlist_management::swap_song(&self.title, i - 1)?;
+                    self.swap_in_file(i - 1)?;
                     self.songs.swap(i - 1, i);
                     self.select_prev();
                 }
@@ -267,22 +363,77 @@ impl<'t> SongsPane<'t> {
             },
             SwapSongDown if self.filter.is_empty() => match self.selected_index() {
                 Some(i) if i + 1 < self.songs.len() => {
-                    m3u::playlist_management::swap_song(&self.title, i)?;
+                    self.swap_in_file(i)?;
                     self.songs.swap(i, i + 1);
                     self.select_next();
                 }
                 _ => {}
             },
+            MoveSongToTop => {
+                if let Some(i) = self.selected_index() {
+                    self.move_song(i, 0)?;
+                }
+            }
+            MoveSongToBottom => {
+                if let Some(i) = self.selected_index() {
+                    self.move_song(i, self.songs.len() - 1)?;
+                }
+            }
             NextSortingMode => {
                 self.next_sorting_method();
                 self.refresh_shown();
             }
             Search => self.filter = "/".into(),
+            ToggleMark => {
+                if let Some(i) = self.selected_index() {
+                    if !self.marked.remove(&i) {
+                        self.marked.insert(i);
+                    }
+                    self.select_next();
+                }
+            }
+            VisualMode => match self.visual_anchor {
+                Some(_) => {
+                    self.marked = self.marked();
+                    self.visual_anchor = None;
+                }
+                None => self.visual_anchor = self.selected_index(),
+            },
             _ => {}
         }
         Ok(())
     }
 
+    /// The marked songs, including the ones in the visual mode range, as indices into `songs`
+    pub fn marked(&self) -> BTreeSet<usize> {
+        let mut marked = self.marked.clone();
+        let anchor = self
+            .visual_anchor
+            .and_then(|a| self.shown.items.iter().position(|&i| i == a));
+        if let (Some(anchor), Some(cursor)) = (anchor, self.shown.state.selected()) {
+            let range = anchor.min(cursor)..=anchor.max(cursor);
+            marked.extend(&self.shown.items[range]);
+        }
+        marked
+    }
+
+    /// The songs that commands act on: the marked ones if there are any, or else the selected one.
+    /// These are indices into `songs`, in the order they're in the playlist.
+    pub fn selection(&self) -> Vec<usize> {
+        let marked = self.marked();
+        if marked.is_empty() {
+            self.selected_index().into_iter().collect()
+        } else {
+            marked.into_iter().collect()
+        }
+    }
+
+    pub fn clear_marks(&mut self) {
+        self.marked.clear();
+        self.visual_anchor = None;
+        self.mouse_visual = false;
+    }
+
     /// Handles a key event when the filter is active.
     pub fn handle_filter_key_event(&mut self, event: KeyEvent) -> Result<bool> {
         match event.code {
@@ -313,10 +464,21 @@ impl<'t> SongsPane<'t> {
         chunk: Rect,
         (x, y): (u16, u16),
         kind: MouseEventKind,
+        modifiers: KeyModifiers,
     ) -> Result<()> {
         match kind {
             MouseEventKind::Up(MouseButton::Left) => {
                 self.mouse_press_location = None;
+                self.mouse_press_index = None;
+                // Drop the dragged song where the mouse is
+                if let (Some(from), Some(to)) = (self.dragged_song.take(), self.selected_index()) {
+                    self.move_song(from, to)?;
+                }
+                if self.mouse_visual {
+                    self.marked = self.marked();
+                    self.visual_anchor = None;
+                    self.mouse_visual = false;
+                }
             }
             // If the mouse press (MouseEventKind::Down event) was done on the scrollbar,
             // any drag events will still be handled by the scrollbar, even if the mouse
@@ -332,7 +494,7 @@ impl<'t> SongsPane<'t> {
                     self.click_scrollbar(app, chunk, (x, y), kind)?;
                 } else {
                     // Clicked on the song list
-                    self.click_list(app, chunk, (x, y), kind)?;
+                    self.click_list(app, chunk, (x, y), kind, modifiers)?;
                 }
             }
             _ => {}
@@ -365,6 +527,7 @@ impl<'t> SongsPane<'t> {
         chunk: Rect,
         (_x, y): (u16, u16),
         kind: MouseEventKind,
+        modifiers: KeyModifiers,
     ) -> Result<()> {
         if let MouseEventKind::Down(MouseButton::Left) = kind {
             self.mouse_press_location = Some(MousePressLocation::List);
@@ -388,6 +551,36 @@ impl<'t> SongsPane<'t> {
             return Ok(());
         }
 
+        match kind {
+            // Shift+click marks every song from the selected one to the clicked one
+            MouseEventKind::Down(MouseButton::Left)
+                if modifiers.contains(KeyModifiers::SHIFT) && self.visual_anchor.is_none() =>
+            {
+                self.visual_anchor = self.selected_index();
+                self.mouse_visual = true;
+            }
+            // and keeps the marks from the same anchor if there already is one
+            MouseEventKind::Down(MouseButton::Left) if modifiers.contains(KeyModifiers::SHIFT) => {}
+            MouseEventKind::Down(MouseButton::Left) => {
+                let song = self.shown.items[index];
+                self.mouse_press_index = Some(song);
+                // Dragging the selected song moves it, unless the songs are sorted some other way
+                let reorderable = matches!(self.sorting_method, SortingMethod::Index);
+                self.dragged_song =
+                    (reorderable && self.selected_index() == Some(song)).then_some(song);
+            }
+            // Dragging over other songs marks them
+            MouseEventKind::Drag(MouseButton::Left)
+                if self.dragged_song.is_none()
+                    && self.visual_anchor.is_none()
+                    && self.mouse_press_index != Some(self.shown.items[index]) =>
+            {
+                self.visual_anchor = self.mouse_press_index;
+                self.mouse_visual = true;
+            }
+            _ => {}
+        }
+
         // Select song
         self.select_index(Some(index));
 
@@ -401,6 +594,7 @@ impl<'t> SongsPane<'t> {
     pub fn play_selected(&self, app: &mut App) -> Result<()> {
         if let Some(song) = self.selected_item() {
             app.player.play(&song.path)?;
+            app.shuffle = order::Shuffle::default();
         }
         Ok(())
     }
@@ -424,6 +618,15 @@ impl<'t> SongsPane<'t> {
     pub fn selected_index(&self) -> Option<usize> {
         self.shown.selected_item()
     }
+
+    /// Indices of the songs that pass the filter, in the order they're shown
+    pub fn shown_indices(&self) -> &[usize] {
+        &self.shown.items
+    }
+
+    pub fn songs(&self) -> &[m3u::Song] {
+        &self.songs
+    }
 }
 
 impl<'t> Component for SongsPane<'t> {
@@ -444,10 +647,24 @@ impl<'t> Component for SongsPane<'t> {
             SortingMethod::Duration => " [↑ Duration]",
         };
 
+        let marked = self.marked();
+        let marks = match (self.visual_anchor, marked.len()) {
+            (Some(_), n) => format!(" [VISUAL: {}]", n),
+            (None, 0) => String::new(),
+            (None, n) => format!(" [{} marked]", n),
+        };
+
+        let marks = match self.dragged_song {
+            Some(i) if self.selected_index() != Some(i) => {
+                format!(" [moving '{}']", self.songs[i].title)
+            }
+            _ => marks,
+        };
+
         let title = if !self.filter.is_empty() {
-            format!(" {}{} ", self.filter, sorting)
+            format!(" {}{}{} ", self.filter, sorting, marks)
         } else {
-            format!(" {}{} ", self.title, sorting)
+            format!(" {}{}{} ", self.title, sorting, marks)
         };
 
         let border_style = if is_focused {
@@ -468,16 +685,25 @@ impl<'t> Component for SongsPane<'t> {
                 .shown
                 .items
                 .iter()
-                .map(|&i| &self.songs[i])
-                .map(|song| {
-                    Row::new(vec![
+                .map(|&i| {
+                    let song = &self.songs[i];
+                    let row = Row::new(vec![
                         format!(" {}", song.title),
                         format!(
                             "{}:{:02}",
                             song.duration.as_secs() / 60,
                             song.duration.as_secs() % 60
                         ),
-                    ])
+                    ]);
+                    if marked.contains(&i) {
+                        row.style(
+                            Style::default()
+                                .fg(Color::LightYellow)
+                                .add_modifier(Modifier::BOLD),
+                        )
+                    } else {
+                        row
+                    }
                 })
                 .collect();
             let songlist_len = songlist.len();
@@ -512,7 +738,7 @@ impl<'t> Component for SongsPane<'t> {
                 .keybindings
                 .0
                 .iter()
-                .find(|&(_key, &cmd)| cmd == Command::Add)
+                .find(|(_key, cmd)| **cmd == Command::Add)
                 .map(|(key, _)| key.0.as_str())
                 .unwrap_or("a");
 
@@ -559,11 +785,55 @@ impl<'a> MouseHandler for SongsPane<'a> {
         match event.kind {
             MouseEventKind::ScrollUp => self.select_prev(),
             MouseEventKind::ScrollDown => self.select_next(),
-            MouseEventKind::Down(MouseButton::Left) | MouseEventKind::Drag(MouseButton::Left) => {
-                self.click(app, chunk, (event.column, event.row), event.kind)?
-            }
+            MouseEventKind::Down(MouseButton::Left)
+            | MouseEventKind::Drag(MouseButton::Left)
+            | MouseEventKind::Up(MouseButton::Left) => self.click(
+                app,
+                chunk,
+                (event.column, event.row),
+                event.kind,
+                event.modifiers,
+            )?,
             _ => {}
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pane(titles: &[&str]) -> SongsPane<'static> {
+        let mut pane = SongsPane::new();
+        pane.songs = titles
+            .iter()
+            .map(|&title| m3u::Song {
+                title: title.into(),
+                ..Default::default()
+            })
+            .collect();
+        pane.sorting_method = SortingMethod::Title;
+        pane.refresh_shown();
+        pane
+    }
+
+    #[test]
+    fn test_marks() {
+        // Shown as a, b, c, d
+        let mut pane = pane(&["d", "b", "a", "c"]);
+        assert_eq!(pane.selection(), vec![2]);
+
+        pane.marked.insert(0);
+        assert_eq!(pane.selection(), vec![0]);
+
+        // Visual mode from b to c
+        pane.select_index(Some(1));
+        pane.visual_anchor = pane.selected_index();
+        pane.select_index(Some(2));
+        assert_eq!(pane.selection(), vec![0, 1, 3]);
+
+        pane.clear_marks();
+        assert_eq!(pane.selection(), vec![3]);
+    }
+}
@@ -19,7 +19,7 @@ use crate::{
     config::Config,
     error::Result,
     events::{self, Channel},
-    player::{DefaultPlayer, Player},
+    player::{order::Shuffle, DefaultPlayer, Player},
     visualizer::{self, Visualizer},
     widgets::notification::Notification,
 };
@@ -30,28 +30,39 @@ pub mod component;
 pub mod filtered_list;
 pub mod modal;
 pub mod playlist_screen;
+pub mod session;
 
 use crate::events::Event;
 
 use self::{
     app_screen::AppScreen,
     component::{Component, MouseHandler, MyBackend},
+    session::Session,
 };
 
 const FRAME_DELAY_MS: u16 = 16;
 const HIGH_EVENT_TIMEOUT: u16 = 1000;
 const LOW_EVENT_TIMEOUT: u16 = 17;
+/// How often the session is saved to disk, if it changed
+const SESSION_SAVE_INTERVAL: Duration = Duration::from_secs(10);
+/// How long to wait for a restored song to load before giving up seeking to where it was
+const RESTORE_SEEK_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct App<'a> {
     pub channel: Channel,
     terminal: Terminal<MyBackend>,
     player: DefaultPlayer,
+    shuffle: Shuffle,
     next_render: time::Instant,
     next_poll_timeout: u16,
     notification: Notification<'a>,
     visualizer: Option<Visualizer>,
     screen: Rc<RefCell<AppScreen<'a>>>,
     quit: bool,
+    /// Seconds to seek to once the restored song is loaded, and when to give up
+    pending_seek: Option<(i64, time::Instant)>,
+    next_session_save: time::Instant,
+    session_changed: bool,
 }
 
 impl<'a> App<'a> {
@@ -71,17 +82,29 @@ impl<'a> App<'a> {
 
         let notification = Notification::default();
 
-        Ok(App {
+        let mut app = App {
             channel,
             terminal,
             player,
+            shuffle: Shuffle::default(),
             next_render,
             next_poll_timeout,
             notification,
             visualizer: None,
             screen,
             quit: false,
-        })
+            pending_seek: None,
+            next_session_save: time::Instant::now() + SESSION_SAVE_INTERVAL,
+            session_changed: false,
+        };
+
+        if let Some(session) = Session::load() {
+            if let Err(e) = app.restore_session(session) {
+                app.notify_err(format!("Couldn't restore the last session: {}", e));
+            }
+        }
+
+        Ok(app)
     }
 
     pub fn run(&mut self) -> Result<()> {
@@ -101,12 +124,20 @@ impl<'a> App<'a> {
         }
 
         reset_terminal()?;
+        if Config::global().persist_session {
+            session::remember(self.session());
+            session::save()?;
+        }
         Ok(())
     }
 
     #[inline]
     fn render(&mut self) -> Result<()> {
         if time::Instant::now() >= self.next_render {
+            if let Some(err) = self.player.take_error() {
+                self.notify_err(err.to_string());
+            }
+
             self.terminal.draw(|frame| {
                 let chunk = frame.size();
                 self.screen.borrow_mut().render(frame, chunk, ());
@@ -193,10 +224,15 @@ impl<'a> App<'a> {
     }
 
     fn handle_event(&mut self, event: events::Event) -> Result<()> {
+        if let Event::SecondTick = event {
+            self.update_session()?;
+        }
+
         match &event {
             Event::Command(command::Command::ToggleVisualizer) => {
                 self.toggle_visualizer()?;
             }
+            Event::Error(message) => self.notify_err(message.clone()),
             Event::Terminal(crossterm::event::Event::Mouse(mouse_event)) => {
                 let screen = self.screen.clone();
                 let chunk = self.frame_size();
@@ -226,10 +262,8 @@ impl<'a> App<'a> {
 
     fn toggle_visualizer(&mut self) -> Result<()> {
         if self.visualizer.take().is_none() {
-            let opts = crate::visualizer::CavaOptions {
-                bars: self.terminal.get_frame().size().width as usize / 2,
-            };
-            self.visualizer = Some(Visualizer::new(opts)?);
+            let bars = self.terminal.get_frame().size().width as usize / 2;
+            self.visualizer = Some(Visualizer::from_config(self.player.sample_source(), bars)?);
         }
         Ok(())
     }
@@ -239,11 +273,100 @@ impl<'a> App<'a> {
 
         std::panic::set_hook(Box::new(move |panic| {
             reset_terminal().unwrap();
+            session::save().ok();
             original_hook(panic);
             std::process::exit(1);
         }));
     }
 
+    ///////////////////////////
+    //        Session        //
+    ///////////////////////////
+    fn session(&self) -> Session {
+        let player = &self.player;
+        let queue: Vec<String> = (0..player.playlist_count().unwrap_or_default())
+            .filter_map(|i| player.playlist_track_path(i).ok())
+            .collect();
+        let position = player
+            .playlist_position()
+            .ok()
+            .filter(|&i| i < queue.len());
+        let time_pos = match self.pending_seek {
+            Some((seconds, _)) => seconds,
+            None => player.time_pos().unwrap_or_default(),
+        };
+
+        Session {
+            queue,
+            position,
+            time_pos,
+            shuffled_from: self.shuffle.original().map(<[String]>::to_vec),
+            volume: player.volume().ok(),
+            browse: self.screen.borrow().browse_session(),
+        }
+    }
+
+    /// Queues the songs of a previous session, paused where they were
+    fn restore_session(&mut self, session: Session) -> Result<()> {
+        self.screen
+            .borrow_mut()
+            .restore_browse_session(session.browse)?;
+        if let Some(volume) = session.volume {
+            self.player.set_volume(volume)?;
+        }
+        if session.queue.is_empty() {
+            return Ok(());
+        }
+
+        if !self.player.paused()? {
+            self.player.toggle_pause()?;
+        }
+        for path in &session.queue {
+            self.player.queue(path)?;
+        }
+        self.shuffle = Shuffle::restored(session.shuffled_from);
+        if let Some(i) = session.position.filter(|&i| i < session.queue.len()) {
+            if i > 0 {
+                self.player.playlist_play_index(i)?;
+            }
+            if session.time_pos > 0 {
+                let deadline = time::Instant::now() + RESTORE_SEEK_TIMEOUT;
+                self.pending_seek = Some((session.time_pos, deadline));
+                self.seek_to_pending();
+            }
+        }
+        Ok(())
+    }
+
+    /// Seeks to where the restored song was, if it has loaded
+    fn seek_to_pending(&mut self) {
+        if let Some((seconds, deadline)) = self.pending_seek {
+            // The player can't seek until the song is loaded, and it has no position before that
+            let loaded = self.player.time_pos().is_ok();
+            if (loaded && self.player.seek(seconds as f64).is_ok())
+                || time::Instant::now() >= deadline
+            {
+                self.pending_seek = None;
+            }
+        }
+    }
+
+    /// Called every second. Saves the session to disk every once in a while, if it changed.
+    fn update_session(&mut self) -> Result<()> {
+        if !Config::global().persist_session {
+            return Ok(());
+        }
+
+        self.seek_to_pending();
+        self.session_changed |= session::remember(self.session());
+        if self.session_changed && time::Instant::now() >= self.next_session_save {
+            self.session_changed = false;
+            self.next_session_save = time::Instant::now() + SESSION_SAVE_INTERVAL;
+            session::save()?;
+        }
+        Ok(())
+    }
+
     pub fn select_screen(&mut self, screen: app_screen::Selected) {
         self.screen.borrow_mut().select(screen);
     }
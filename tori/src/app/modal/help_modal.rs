@@ -45,7 +45,7 @@ impl HelpModal {
                 let make_cell = |(k, v): &(&InputStr, &Command)| {
                     Line::from(vec![
                         Span::styled(pad(&k.0), Style::default().fg(Color::LightBlue)),
-                        Span::raw(format!(" {:?}", v)),
+                        Span::raw(format!(" {}", v)),
                     ])
                 };
 
@@ -2,11 +2,13 @@ pub mod confirmation_modal;
 pub mod help_modal;
 pub mod hotkey_modal;
 pub mod input_modal;
+pub mod select_modal;
 
 pub use confirmation_modal::ConfirmationModal;
 pub use help_modal::HelpModal;
 pub use hotkey_modal::HotkeyModal;
 pub use input_modal::InputModal;
+pub use select_modal::SelectModal;
 
 use tui::{layout::Rect, style::Style, Frame};
 
@@ -133,4 +135,40 @@ mod tests {
             Some(Message::Quit)
         );
     }
+
+    #[test]
+    fn test_select_modal_lifecycle() {
+        let items = vec!["speakers".to_string(), "headphones".to_string()];
+        let mut modal = SelectModal::new(" Select ", items);
+        assert_eq!(
+            modal.handle_event(Event::Terminal(key_event(Char('j')))).ok(),
+            Some(Message::Nothing)
+        );
+        assert_eq!(
+            modal.handle_event(Event::Terminal(key_event(Enter))).ok(),
+            Some(Message::Commit("headphones".into()))
+        );
+
+        // Wraps around
+        modal.handle_event(Event::Terminal(key_event(Char('j')))).ok();
+        assert_eq!(
+            modal.handle_event(Event::Terminal(key_event(Enter))).ok(),
+            Some(Message::Commit("speakers".into()))
+        );
+
+        assert_eq!(
+            modal.handle_event(Event::Terminal(key_event(Esc))).ok(),
+            Some(Message::Quit)
+        );
+    }
+
+    #[test]
+    fn test_select_modal_empty() {
+        let mut modal = SelectModal::new(" Select ", vec![]);
+        modal.handle_event(Event::Terminal(key_event(Char('k')))).ok();
+        assert_eq!(
+            modal.handle_event(Event::Terminal(key_event(Enter))).ok(),
+            Some(Message::Quit)
+        );
+    }
 }
@@ -0,0 +1,109 @@
+use super::{get_modal_chunk, Message, Modal};
+
+use crossterm::event::KeyCode;
+use std::borrow::Cow;
+use tui::{
+    layout::Alignment,
+    style::{Color, Style},
+    widgets::{Block, BorderType, Borders, Clear, List, ListItem, ListState},
+    Frame,
+};
+
+use crate::{
+    app::component::{Mode, MyBackend},
+    error::Result,
+    events::Event,
+};
+
+/// Maximum number of items shown at once
+const MAX_SHOWN: u16 = 10;
+
+/// A modal box that asks the user to pick one item from a list
+#[derive(Debug, Default)]
+pub struct SelectModal<'t> {
+    title: Cow<'t, str>,
+    items: Vec<String>,
+    state: ListState,
+    style: Style,
+}
+
+impl<'t> SelectModal<'t> {
+    pub fn new<T>(title: T, items: Vec<String>) -> Self
+    where
+        T: Into<Cow<'t, str>>,
+    {
+        let mut state = ListState::default();
+        state.select((!items.is_empty()).then_some(0));
+        Self {
+            title: title.into(),
+            items,
+            state,
+            style: Style::default().fg(Color::LightBlue),
+        }
+    }
+
+    fn select_offset(&mut self, offset: isize) {
+        if self.items.is_empty() {
+            return;
+        }
+        let len = self.items.len() as isize;
+        let i = self.state.selected().unwrap_or(0) as isize;
+        self.state.select(Some((i + offset).rem_euclid(len) as usize));
+    }
+}
+
+impl<'t> Modal for SelectModal<'t> {
+    fn apply_style(&mut self, style: Style) {
+        self.style = style;
+    }
+
+    fn handle_event(&mut self, event: Event) -> Result<Message> {
+        use KeyCode::*;
+        if let Event::Terminal(crossterm::event::Event::Key(event)) = event {
+            match event.code {
+                Esc | Char('q') => return Ok(Message::Quit),
+                Enter => {
+                    return match self.state.selected().and_then(|i| self.items.get(i)) {
+                        Some(item) => Ok(Message::Commit(item.clone())),
+                        None => Ok(Message::Quit),
+                    };
+                }
+                Down | Char('j') => self.select_offset(1),
+                Up | Char('k') => self.select_offset(-1),
+                _ => {}
+            }
+        }
+        Ok(Message::Nothing)
+    }
+
+    fn render(&mut self, frame: &mut Frame<'_, MyBackend>) {
+        let size = frame.size();
+        let mut chunk = get_modal_chunk(size);
+        chunk.height = (self.items.len() as u16).clamp(1, MAX_SHOWN) + 2;
+        chunk.y = size.height.saturating_sub(chunk.height) / 2;
+        chunk.height = chunk.height.min(size.height);
+
+        let block = Block::default()
+            .title(self.title.as_ref())
+            .title_alignment(Alignment::Center)
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .border_style(self.style);
+
+        let items: Vec<_> = self
+            .items
+            .iter()
+            .map(|item| ListItem::new(item.as_str()))
+            .collect();
+        let list = List::new(items)
+            .block(block)
+            .highlight_style(Style::default().bg(Color::LightBlue).fg(Color::Black));
+
+        frame.render_widget(Clear, chunk);
+        frame.render_stateful_widget(list, chunk, &mut self.state);
+    }
+
+    fn mode(&self) -> Mode {
+        Mode::Insert
+    }
+}
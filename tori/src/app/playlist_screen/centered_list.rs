@@ -19,11 +19,15 @@ pub struct CenteredListState {
 }
 
 impl CenteredListState {
-    #[allow(dead_code)]
     pub fn selected(&self) -> Option<usize> {
         self.selected
     }
 
+    /// Index of the first item that's shown
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
     pub fn select(&mut self, index: Option<usize>) {
         self.selected = index;
         if index.is_none() {
@@ -49,7 +53,6 @@ impl<'a> CenteredListItem<'a> {
         }
     }
 
-    #[allow(dead_code)]
     pub fn style(mut self, style: Style) -> CenteredListItem<'a> {
         self.style = style;
         self
@@ -227,7 +230,7 @@ impl<'a> StatefulWidget for CenteredList<'a> {
                 width: list_area.width,
                 height: item.height() as u16,
             };
-            let item_style = self.style; //.patch(item.style);
+            let item_style = self.style.patch(item.style);
             buf.set_style(area, item_style);
 
             let is_selected = state.selected.map(|s| s == i).unwrap_or(false);
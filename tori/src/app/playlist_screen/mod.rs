@@ -1,23 +1,57 @@
 use self::centered_list::{CenteredList, CenteredListItem, CenteredListState};
 use super::{
     component::{Component, MouseHandler},
+    modal::{self, InputModal, Modal, SelectModal},
     App, Mode,
 };
-use crate::{command, error::Result, events, player::Player, widgets::Scrollbar};
+use crate::{
+    command,
+    error::Result,
+    events,
+    m3u::playlist_management,
+    player::{order, Player},
+    rect_ops::RectOps,
+    util::ClickInfo,
+    widgets::Scrollbar,
+};
+use crossterm::event::{MouseButton, MouseEventKind};
 use std::{thread, time::Duration};
 use tui::{
     layout::{Alignment, Rect},
-    style::{Color, Style},
+    style::{Color, Modifier, Style},
     widgets::{Block, BorderType, Borders},
 };
 
 mod centered_list;
 
-/// Screen that shows the current mpv playlist. You can press '2' to access it.
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ModalType {
+    SaveQueue,
+    PlayPlaylist,
+}
+
+/// Screen that shows the current mpv playlist, where songs can be played, removed and reordered.
+/// You can press '2' to access it.
+#[derive(Default)]
 pub struct PlaylistScreen {
     songs: Vec<String>,
-    playing: CenteredListState,
+    /// Index of the song that's playing
+    playing: Option<usize>,
+    /// The song the commands act on
+    selected: CenteredListState,
+    last_click: Option<ClickInfo>,
+    modal: Option<(ModalType, Box<dyn Modal>)>,
+}
+
+impl std::fmt::Debug for PlaylistScreen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PlaylistScreen")
+            .field("songs", &self.songs)
+            .field("playing", &self.playing)
+            .field("selected", &self.selected)
+            .field("modal", &self.modal.as_ref().map(|(modal_type, _)| modal_type))
+            .finish_non_exhaustive()
+    }
 }
 
 impl PlaylistScreen {
@@ -29,15 +63,20 @@ impl PlaylistScreen {
             .map(|i| player.playlist_track_title(i))
             .collect::<Result<_>>()?;
 
-        self.playing.select(player.playlist_position().ok());
+        self.playing = player.playlist_position().ok();
+
+        // Follow the song that's playing until another one is selected
+        let selected = match self.selected.selected() {
+            Some(i) if i < n => Some(i),
+            _ => self.playing.or_else(|| n.checked_sub(1)),
+        };
+        self.selected.select(selected);
 
         Ok(self)
     }
 
     /// Waits a couple of milliseconds, then calls [update](PlaylistScreen::update). It's used
-    /// primarily by [select_next](PlaylistScreen::select_next) and
-    /// [select_prev](PlaylistScreen::select_prev) because mpv takes a while to update the playlist
-    /// properties after changing the selection.
+    /// after changing the playlist, because mpv takes a while to update the playlist properties.
     pub fn update_after_delay(&self, app: &App) {
         let sender = app.channel.sender.clone();
         thread::spawn(move || {
@@ -49,13 +88,70 @@ impl PlaylistScreen {
     fn handle_command(&mut self, app: &mut App, cmd: command::Command) -> Result<()> {
         use command::Command::*;
         match cmd {
-            SelectNext | NextSong => self.select_next(app),
-            SelectPrev | PrevSong => self.select_prev(app),
+            SelectNext => self.select_offset(1),
+            SelectPrev => self.select_offset(-1),
+            Delete => {
+                if let Some(i) = self.selected.selected() {
+                    app.player.playlist_remove(i)?;
+                    self.update_after_delay(app);
+                }
+            }
+            SwapSongDown => self.move_selected(app, |i, _| i + 1)?,
+            SwapSongUp => self.move_selected(app, |i, _| i.wrapping_sub(1))?,
+            MoveSongToTop => self.move_selected(app, |_, _| 0)?,
+            MoveSongToBottom => self.move_selected(app, |_, len| len - 1)?,
+            ClearQueue => {
+                app.player.playlist_clear()?;
+                app.shuffle = order::Shuffle::default();
+                self.selected.select(None);
+                self.update_after_delay(app);
+            }
+            SaveQueue => {
+                if self.songs.is_empty() {
+                    app.notify_err("The queue is empty");
+                } else {
+                    let modal = InputModal::new(" Save the queue to a new or existing playlist ");
+                    self.modal = Some((ModalType::SaveQueue, Box::new(modal)));
+                }
+            }
+            PlayPlaylist(None) => {
+                let playlists = playlist_management::list_playlists()?;
+                let modal = SelectModal::new(" Replace the queue with ", playlists);
+                self.modal = Some((ModalType::PlayPlaylist, Box::new(modal)));
+            }
             _ => {}
         }
         Ok(())
     }
 
+    /// Handles the message returned by the modal, after it handled an event
+    fn handle_modal_message(&mut self, app: &mut App, msg: modal::Message) -> Result<()> {
+        use modal::Message::*;
+        let modal_type = match &self.modal {
+            Some((modal_type, _)) => *modal_type,
+            None => return Ok(()),
+        };
+        match (modal_type, msg) {
+            (_, Nothing) => {}
+            (_, Quit) => self.modal = None,
+            (ModalType::SaveQueue, Commit(playlist)) => {
+                self.modal = None;
+                let count = playlist_management::save_queue(&app.player, &playlist)?;
+                app.notify_ok(format!("Saved {} song(s) to '{}'", count, playlist));
+                app.channel.sender.send(events::Event::ChangedPlaylist)?;
+            }
+            (ModalType::PlayPlaylist, Commit(playlist)) => {
+                self.modal = None;
+                let count = playlist_management::play_playlist(&mut app.player, &playlist)?;
+                app.shuffle = order::Shuffle::default();
+                app.notify_ok(format!("Playing {} song(s) from '{}'", count, playlist));
+                self.selected.select(None);
+                self.update_after_delay(app);
+            }
+        }
+        Ok(())
+    }
+
     fn handle_terminal_event(
         &mut self,
         app: &mut App,
@@ -64,26 +160,47 @@ impl PlaylistScreen {
         use crossterm::event::{Event, KeyCode};
         if let Event::Key(key_event) = event {
             match key_event.code {
-                KeyCode::Up => self.select_prev(app),
-                KeyCode::Down => self.select_next(app),
+                KeyCode::Up => self.select_offset(-1),
+                KeyCode::Down => self.select_offset(1),
+                KeyCode::Enter => self.play_selected(app)?,
                 _ => {}
             }
         }
         Ok(())
     }
 
-    pub fn select_next(&self, app: &mut App) {
-        app.player
-            .playlist_next()
-            .unwrap_or_else(|_| app.notify_err("No next song"));
-        self.update_after_delay(app);
+    pub fn play_selected(&self, app: &mut App) -> Result<()> {
+        if let Some(i) = self.selected.selected() {
+            app.player.playlist_play_index(i)?;
+            self.update_after_delay(app);
+        }
+        Ok(())
     }
 
-    pub fn select_prev(&self, app: &mut App) {
-        app.player
-            .playlist_previous()
-            .unwrap_or_else(|_| app.notify_err("No previous song"));
-        self.update_after_delay(app);
+    fn select_offset(&mut self, offset: isize) {
+        if self.songs.is_empty() {
+            return;
+        }
+        let len = self.songs.len() as isize;
+        let i = self.selected.selected().unwrap_or(0) as isize;
+        self.selected
+            .select(Some((i + offset).rem_euclid(len) as usize));
+    }
+
+    /// Moves the selected song to the index returned by `to(selected, len)`, if it's in bounds,
+    /// and keeps it selected
+    fn move_selected(&mut self, app: &mut App, to: fn(usize, usize) -> usize) -> Result<()> {
+        if let Some(i) = self.selected.selected() {
+            let j = to(i, self.songs.len());
+            if j < self.songs.len() && j != i {
+                app.player.playlist_move(i, j)?;
+                let song = self.songs.remove(i);
+                self.songs.insert(j, song);
+                self.selected.select(Some(j));
+                self.update_after_delay(app);
+            }
+        }
+        Ok(())
     }
 }
 
@@ -91,7 +208,10 @@ impl Component for PlaylistScreen {
     type RenderState = ();
 
     fn mode(&self) -> Mode {
-        Mode::Normal
+        match &self.modal {
+            Some((_, modal)) => modal.mode(),
+            None => Mode::Normal,
+        }
     }
 
     fn render(&mut self, frame: &mut tui::Frame<'_, super::MyBackend>, chunk: Rect, (): ()) {
@@ -105,7 +225,19 @@ impl Component for PlaylistScreen {
         let items: Vec<_> = self
             .songs
             .iter()
-            .map(|x| CenteredListItem::new(x.as_str()))
+            .enumerate()
+            .map(|(i, x)| {
+                let item = CenteredListItem::new(x.as_str());
+                if Some(i) == self.playing {
+                    item.style(
+                        Style::default()
+                            .fg(Color::LightRed)
+                            .add_modifier(Modifier::BOLD),
+                    )
+                } else {
+                    item
+                }
+            })
             .collect();
         let list = CenteredList::new(items)
             .block(block)
@@ -113,19 +245,27 @@ impl Component for PlaylistScreen {
             .highlight_symbol("›")
             .highlight_symbol_right("‹");
 
-        frame.render_stateful_widget(list, chunk, &mut self.playing);
+        frame.render_stateful_widget(list, chunk, &mut self.selected);
 
         if self.songs.len() > chunk.height as usize - 2 {
-            if let Some(index) = self.playing.selected() {
+            if let Some(index) = self.selected.selected() {
                 let scrollbar = Scrollbar::new(index as u16, self.songs.len() as u16)
                     .with_style(Style::default().fg(Color::Red));
                 frame.render_widget(scrollbar, chunk);
             }
         }
+
+        if let Some((_, modal)) = &mut self.modal {
+            modal.render(frame);
+        }
     }
 
     fn handle_event(&mut self, app: &mut App, event: events::Event) -> Result<()> {
         use events::Event::*;
+        if let (Some((_, modal)), Command(_) | Terminal(_)) = (&mut self.modal, &event) {
+            let msg = modal.handle_event(event)?;
+            return self.handle_modal_message(app, msg);
+        }
         match event {
             Command(cmd) => self.handle_command(app, cmd)?,
             Terminal(event) => self.handle_terminal_event(app, event)?,
@@ -141,10 +281,31 @@ impl Component for PlaylistScreen {
 impl MouseHandler for PlaylistScreen {
     fn handle_mouse(
         &mut self,
-        _app: &mut App,
-        _chunk: Rect,
-        _event: crossterm::event::MouseEvent,
+        app: &mut App,
+        chunk: Rect,
+        event: crossterm::event::MouseEvent,
     ) -> Result<()> {
+        if self.modal.is_some() {
+            // No modal clicks for now
+            return Ok(());
+        }
+        match event.kind {
+            MouseEventKind::ScrollUp => self.select_offset(-1),
+            MouseEventKind::ScrollDown => self.select_offset(1),
+            MouseEventKind::Down(MouseButton::Left) if chunk.contains(event.column, event.row) => {
+                let top = chunk.top() + 1;
+                let index = event.row.saturating_sub(top) as usize + self.selected.offset();
+                let click = ClickInfo::update(&mut self.last_click, event.row);
+                if event.row >= top && index < self.songs.len() {
+                    self.selected.select(Some(index));
+                    // Double click plays the song
+                    if click.double_click {
+                        self.play_selected(app)?;
+                    }
+                }
+            }
+            _ => {}
+        }
         Ok(())
     }
 }
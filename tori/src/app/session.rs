@@ -0,0 +1,91 @@
+//! What's restored when tori starts again: the queue, where it was in it, the volume and what the
+//! browse screen was showing. Unless `persist_session` is off in the config, it's saved to the
+//! state directory every few seconds, when tori quits and when it panics.
+
+use std::{fs, path::PathBuf, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use super::browse_screen::BrowseSession;
+use crate::{config::Config, error::Result};
+
+/// The latest session, so the panic hook can save it
+static LATEST: Mutex<Option<Session>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// Paths (or URLs) of the songs in the queue
+    pub queue: Vec<String>,
+    /// Index of the current song in `queue`
+    pub position: Option<usize>,
+    /// Seconds into the current song
+    pub time_pos: i64,
+    /// The queue before it was shuffled, if it is
+    pub shuffled_from: Option<Vec<String>>,
+    pub volume: Option<i64>,
+    pub browse: BrowseSession,
+}
+
+impl Session {
+    fn path() -> Option<PathBuf> {
+        Config::global()
+            .persist_session
+            .then(Config::state_dir)
+            .flatten()
+            .map(|dir| dir.join("session.json"))
+    }
+
+    /// The session saved last time, if there is one and `persist_session` is on
+    pub fn load() -> Option<Self> {
+        Session::path()
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(path) = Session::path() {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, serde_json::to_vec(self)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps `session` as the latest one. Returns whether it changed.
+pub fn remember(session: Session) -> bool {
+    let mut latest = LATEST.lock().unwrap();
+    let changed = latest.as_ref() != Some(&session);
+    *latest = Some(session);
+    changed
+}
+
+/// Saves the latest session to disk
+pub fn save() -> Result<()> {
+    // The panic hook may run while the lock is held, so don't wait for it
+    match LATEST.try_lock() {
+        Ok(latest) => latest.as_ref().map_or(Ok(()), Session::save),
+        Err(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_fields_use_defaults() {
+        let session: Session =
+            serde_json::from_str(r#"{"queue": ["a.mp3", "b.mp3"], "position": 1}"#).unwrap();
+        assert_eq!(session.queue, ["a.mp3", "b.mp3"]);
+        assert_eq!(session.position, Some(1));
+        assert_eq!(session.time_pos, 0);
+        assert_eq!(session.shuffled_from, None);
+        assert_eq!(session.browse, BrowseSession::default());
+
+        let json = serde_json::to_string(&session).unwrap();
+        assert_eq!(serde_json::from_str::<Session>(&json).unwrap(), session);
+    }
+}
@@ -1,6 +1,11 @@
-use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Debug, Default, Clone, Copy, PartialEq, Hash, Serialize, Deserialize)]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Something that can be bound to a key in the config. Commands with an argument are written like
+/// `{Seek: 60}` (or `!Seek 60`).
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub enum Command {
     #[default]
     Nop,
@@ -8,6 +13,7 @@ pub enum Command {
     NextSong,
     PrevSong,
     TogglePause,
+    /// Cycle between repeat off, repeating the queue and repeating the current song
     ToggleLoop,
     SeekForward,
     SeekBackward,
@@ -34,7 +40,41 @@ pub enum Command {
     /// Swap the selected song with the one above it
     SwapSongUp,
 
-    /// Shuffle current playlist
+    /// Play the selected (or marked) songs after the current one
+    QueueNext,
+
+    /// Remove every song from the queue, except the one that's playing
+    ClearQueue,
+
+    /// Add the songs in the queue to a playlist, which is created if it doesn't exist
+    SaveQueue,
+
+    /// Replace the queue with the songs of a playlist. `{PlayPlaylist: "name"}` plays the given
+    /// playlist, otherwise it's the selected one or one chosen from a list.
+    PlayPlaylist(Option<String>),
+
+    /// Seek forward (or backward, if negative) by some seconds, like `{Seek: 60}`
+    Seek(f64),
+
+    /// Seek to a percentage of the current song, like `{SeekPercent: 50}`
+    SeekPercent(usize),
+
+    /// Change the volume by some percentage points, like `{AddVolume: -10}`
+    AddVolume(isize),
+
+    /// Set the volume to a percentage, like `{SetVolume: 30}`
+    SetVolume(i64),
+
+    /// Move the selected song to a position in the playlist, typed in a modal
+    MoveSongTo,
+
+    /// Move the selected song to the top of the playlist
+    MoveSongToTop,
+
+    /// Move the selected song to the bottom of the playlist
+    MoveSongToBottom,
+
+    /// Shuffle the songs after the current one, or put the queue back in its original order
     Shuffle,
 
     /// Select next item (like a song or playlist)
@@ -67,6 +107,99 @@ pub enum Command {
     /// Filter/search the selected pane (playlists or songs).
     /// The same as pressing '/'
     Search,
+
+    /// Choose the audio output device from a list. Playback moves to it without restarting the
+    /// song.
+    SelectAudioDevice,
+
+    /// Copy the selected playlist to a new one
+    DuplicatePlaylist,
+
+    /// Add the songs of the selected playlist to another one, chosen from a list
+    MergePlaylist,
+
+    /// Mark or unmark the selected song. Commands like Delete, Rename, QueueSong and
+    /// CopyToPlaylist act on every marked song.
+    ToggleMark,
+
+    /// Start or stop marking every song between the current one and the selected one, like vim's
+    /// visual mode
+    VisualMode,
+
+    /// Copy the selected (or marked) songs to another playlist, chosen from a list
+    CopyToPlaylist,
+
+    /// Move the selected (or marked) songs to another playlist, chosen from a list
+    MoveToPlaylist,
+
+    /// Copy all songs that are shown (i.e. that pass the filter) to another playlist
+    CopyShownToPlaylist,
+
+    /// Move all songs that are shown (i.e. that pass the filter) to another playlist
+    MoveShownToPlaylist,
+
+    /// Undo the last change to a playlist (like deleting, renaming or moving a song, or deleting a
+    /// playlist)
+    Undo,
+
+    /// Redo the last undone change to a playlist
+    Redo,
+}
+
+impl Serialize for Command {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Command::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Command {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        use serde_yaml::{
+            value::{Tag, TaggedValue},
+            Value,
+        };
+
+        let with_argument = |name: String, value: Value| {
+            Value::Tagged(Box::new(TaggedValue {
+                tag: Tag::new(name),
+                value,
+            }))
+        };
+
+        let value = match Value::deserialize(deserializer)? {
+            // serde_yaml only understands arguments in tags, like `!Seek 60`
+            Value::Mapping(map) if map.len() == 1 => {
+                let (name, argument) = map.into_iter().next().unwrap();
+                match name {
+                    Value::String(name) => with_argument(name, argument),
+                    _ => return Err(D::Error::custom("command names must be strings")),
+                }
+            }
+            // Commands with an optional argument, like PlayPlaylist, can be written without it
+            Value::String(name) => {
+                return Command::deserialize(Value::String(name.clone()))
+                    .or_else(|e| Command::deserialize(with_argument(name, Value::Null)).map_err(|_| e))
+                    .map_err(D::Error::custom);
+            }
+            value => value,
+        };
+        Command::deserialize(value).map_err(D::Error::custom)
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::PlayPlaylist(None) => write!(f, "PlayPlaylist"),
+            Command::PlayPlaylist(Some(playlist)) => write!(f, "PlayPlaylist {}", playlist),
+            Command::Seek(seconds) => write!(f, "Seek {}", seconds),
+            Command::SeekPercent(percent) => write!(f, "SeekPercent {}", percent),
+            Command::AddVolume(x) => write!(f, "AddVolume {}", x),
+            Command::SetVolume(x) => write!(f, "SetVolume {}", x),
+            other => write!(f, "{:?}", other),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -81,6 +214,10 @@ mod tests {
             serde_yaml::to_string(&Command::TogglePause).unwrap(),
             "TogglePause\n"
         );
+        assert_eq!(
+            serde_yaml::to_string(&Command::SetVolume(30)).unwrap(),
+            "!SetVolume 30\n"
+        );
     }
 
     #[test]
@@ -93,5 +230,31 @@ mod tests {
             serde_yaml::from_str::<Command>("VolumeUp").unwrap(),
             Command::VolumeUp
         );
+        assert!(serde_yaml::from_str::<Command>("Qiut").is_err());
+    }
+
+    #[test]
+    fn test_deserialization_with_arguments() {
+        let parse = |s| serde_yaml::from_str::<Command>(s);
+        assert_eq!(parse("{Seek: 60}").unwrap(), Command::Seek(60.));
+        assert_eq!(parse("Seek: -2.5").unwrap(), Command::Seek(-2.5));
+        assert_eq!(parse("!SetVolume 30").unwrap(), Command::SetVolume(30));
+        assert_eq!(parse("{SeekPercent: 50}").unwrap(), Command::SeekPercent(50));
+        assert_eq!(
+            parse("{PlayPlaylist: focus}").unwrap(),
+            Command::PlayPlaylist(Some("focus".into()))
+        );
+
+        // The argument of PlayPlaylist is optional, but the others aren't
+        assert_eq!(parse("PlayPlaylist").unwrap(), Command::PlayPlaylist(None));
+        assert!(parse("Seek").is_err());
+        assert!(parse("{Seek: far}").is_err());
+        assert!(parse("{Quit: 1}").is_err());
+
+        let shortcuts: std::collections::HashMap<String, Command> =
+            serde_yaml::from_str("A-right: {Seek: 60}\n'0': {SetVolume: 0}\nq: Quit").unwrap();
+        assert_eq!(shortcuts["A-right"], Command::Seek(60.));
+        assert_eq!(shortcuts["0"], Command::SetVolume(0));
+        assert_eq!(shortcuts["q"], Command::Quit);
     }
 }
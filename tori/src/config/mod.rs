@@ -10,8 +10,39 @@ use shortcuts::Shortcuts;
 pub struct Config {
     pub playlists_dir: String,
     pub visualizer_gradient: [(u8, u8, u8); 2],
+    pub visualizer_backend: VisualizerBackend,
+    pub visualizer_noise_reduction: f32,
     pub keybindings: Shortcuts,
     pub mpv_ao: Option<String>,
+    pub cpal_host: Option<String>,
+    pub cpal_device: Option<String>,
+    pub replaygain: ReplayGain,
+    pub persist_undo_history: bool,
+    pub persist_session: bool,
+}
+
+/// What computes the visualizer bars
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VisualizerBackend {
+    /// Native if the player gives us its samples, cava otherwise
+    #[default]
+    Auto,
+    /// An external cava process
+    Cava,
+    /// An in-process spectrum analyzer, reading the player's samples or capturing the system's
+    /// audio with PulseAudio/PipeWire
+    Native,
+}
+
+/// Which ReplayGain values the player applies to the tracks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayGain {
+    Track,
+    Album,
+    #[default]
+    Off,
 }
 
 static INSTANCE: OnceCell<Config> = OnceCell::new();
@@ -29,6 +60,13 @@ impl Config {
         PathBuf::from(&Config::global().playlists_dir).join(format!("{}.m3u8", playlist_name))
     }
 
+    /// Where tori keeps things between sessions, like the undo history and the last session
+    pub fn state_dir() -> Option<PathBuf> {
+        dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .map(|dir| dir.join("tori"))
+    }
+
     pub fn merge(mut self, other: OptionalConfig) -> Self {
         if let Some(playlists_dir) = other.playlists_dir {
             self.playlists_dir = playlists_dir;
@@ -53,7 +91,29 @@ impl Config {
             self.visualizer_gradient = [color_at(0), color_at(1)];
         }
 
+        if let Some(visualizer_backend) = other.visualizer_backend {
+            self.visualizer_backend = visualizer_backend;
+        }
+
+        if let Some(visualizer_noise_reduction) = other.visualizer_noise_reduction {
+            self.visualizer_noise_reduction = visualizer_noise_reduction;
+        }
+
         self.mpv_ao = other.mpv_ao;
+        self.cpal_host = other.cpal_host;
+        self.cpal_device = other.cpal_device;
+
+        if let Some(replaygain) = other.replaygain {
+            self.replaygain = replaygain;
+        }
+
+        if let Some(persist_undo_history) = other.persist_undo_history {
+            self.persist_undo_history = persist_undo_history;
+        }
+
+        if let Some(persist_session) = other.persist_session {
+            self.persist_session = persist_session;
+        }
 
         self
     }
@@ -108,8 +168,15 @@ impl Color {
 pub struct OptionalConfig {
     pub playlists_dir: Option<String>,
     pub visualizer_gradient: Option<[Color; 2]>,
+    pub visualizer_backend: Option<VisualizerBackend>,
+    pub visualizer_noise_reduction: Option<f32>,
     pub keybindings: Option<Shortcuts>,
     pub mpv_ao: Option<String>,
+    pub cpal_host: Option<String>,
+    pub cpal_device: Option<String>,
+    pub replaygain: Option<ReplayGain>,
+    pub persist_undo_history: Option<bool>,
+    pub persist_session: Option<bool>,
 }
 
 impl OptionalConfig {
@@ -12,6 +12,8 @@ use super::command::Command;
 pub enum Event {
     SecondTick,
     SongAdded { playlist: String, song: String },
+    /// Something failed in a background thread, and the message should be shown to the user
+    Error(String),
     ChangedPlaylist,
     Command(Command),
     Terminal(CrosstermEvent),
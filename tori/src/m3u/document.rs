@@ -0,0 +1,391 @@
+use std::{fmt, fs, path::Path};
+
+use super::parser::{self, title_comma, Warning};
+use super::{Parser, Song};
+use crate::error::Result;
+
+////////////////////////////
+//        Document        //
+////////////////////////////
+/// A playlist file that remembers every line as it was read. Editing some entries leaves the rest
+/// of the file byte-for-byte the same, so hand-edited playlists keep their blank lines, fractional
+/// durations and directives tori doesn't know about.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    /// The #EXTM3U line, if there is one
+    header: String,
+    entries: Vec<Entry>,
+    /// Whatever comes after the last song, like blank lines or directives without a path
+    trailer: String,
+    /// Line ending of the file, used for the lines we write
+    newline: &'static str,
+    warnings: Vec<Warning>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    song: Song,
+    /// The lines of the entry with their line endings. The last one is the song path, and the
+    /// ones before it are directives, comments and blank lines.
+    lines: Vec<String>,
+}
+
+impl Document {
+    pub fn parse(content: &str) -> parser::Result<Self> {
+        let newline = if content.contains("\r\n") { "\r\n" } else { "\n" };
+        let mut parser = Parser::from_string(content);
+
+        // When there's no header, the parser has peeked at the first line without consuming it
+        let header_end = if parser.next_header()? {
+            parser.cursor()
+        } else {
+            0
+        };
+
+        let mut entries = Vec::new();
+        let mut start = header_end;
+        while let Some(song) = parser.next_song()? {
+            let end = parser.cursor();
+            entries.push(Entry {
+                song,
+                lines: content[start..end]
+                    .split_inclusive('\n')
+                    .map(String::from)
+                    .collect(),
+            });
+            start = end;
+        }
+
+        Ok(Self {
+            header: content[..header_end].to_string(),
+            entries,
+            trailer: content[start..].to_string(),
+            newline,
+            warnings: parser.warnings().to_vec(),
+        })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        Ok(Self::parse(&content)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    /// Adds an #EXTM3U line to the top of the file, if it doesn't have one
+    pub fn add_header(&mut self) {
+        if self.header.is_empty() {
+            self.header = format!("#EXTM3U{}", self.newline);
+        }
+    }
+
+    /// Problems found when parsing the file
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Song> {
+        self.entries.get(index).map(|e| &e.song)
+    }
+
+    pub fn songs(&self) -> impl Iterator<Item = &Song> {
+        self.entries.iter().map(|e| &e.song)
+    }
+
+    /// Inserts a song at `index`, shifting the ones after it.
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, song: Song) {
+        let lines = song
+            .serialize()
+            .lines()
+            .map(|line| format!("{}{}", line, self.newline))
+            .collect();
+        self.entries.insert(index, Entry { song, lines });
+    }
+
+    pub fn push(&mut self, song: Song) {
+        self.insert(self.len(), song);
+    }
+
+    /// The lines of the song at `index`, as they are in the file
+    pub fn entry_text(&self, index: usize) -> Option<String> {
+        let mut text = self.entries.get(index)?.lines.concat();
+        if !text.ends_with('\n') {
+            text += self.newline;
+        }
+        Some(text)
+    }
+
+    /// Inserts the songs in `text` (usually from [Document::entry_text]) at `index`, keeping their
+    /// lines as they are. Returns how many songs were inserted.
+    /// Panics if `index > len`.
+    pub fn insert_text(&mut self, index: usize, text: &str) -> parser::Result<usize> {
+        let other = Self::parse(text)?;
+        let count = other.entries.len();
+        self.entries.splice(index..index, other.entries);
+        Ok(count)
+    }
+
+    /// Removes the song at `index`, along with the blank lines before it
+    pub fn remove(&mut self, index: usize) -> Option<Song> {
+        (index < self.len()).then(|| self.entries.remove(index).song)
+    }
+
+    /// Moves the song at `from` so it ends up at index `to`.
+    /// Panics if either index is out of bounds.
+    pub fn move_entry(&mut self, from: usize, to: usize) {
+        let entry = self.entries.remove(from);
+        self.entries.insert(to, entry);
+    }
+
+    /// Panics if either index is out of bounds.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.entries.swap(a, b);
+    }
+
+    /// Changes the song at `index`. Only the lines of the fields that actually changed are
+    /// rewritten. For example, renaming a song keeps its duration and attributes as they were
+    /// written. Panics if `index` is out of bounds.
+    pub fn update(&mut self, index: usize, f: impl FnOnce(&mut Song)) {
+        let newline = self.newline;
+        let entry = &mut self.entries[index];
+        let old = entry.song.clone();
+        let mut song = old.clone();
+        f(&mut song);
+
+        if song.duration != old.duration || song.attributes != old.attributes {
+            entry.set_extinf(song.extinf_line(), newline);
+        } else if song.title != old.title {
+            let line = match entry.extinf_index() {
+                Some(i) => {
+                    let line = &entry.lines[i];
+                    let content = line.trim_end_matches(['\r', '\n']);
+                    let prefix_len = "#EXTINF:".len();
+                    match title_comma(&content[prefix_len..]) {
+                        Some(comma) => format!(
+                            "{},{}",
+                            &content[..prefix_len + comma],
+                            song.title
+                        ),
+                        None => format!("{},{}", content, song.title),
+                    }
+                }
+                None => song.extinf_line(),
+            };
+            entry.set_extinf(line, newline);
+        }
+
+        if song.group != old.group
+            || song.album != old.album
+            || song.artist != old.artist
+            || song.vlc_options != old.vlc_options
+            || song.extra != old.extra
+        {
+            entry.lines.retain(|line| !is_other_directive(line));
+            let path_index = entry.lines.len() - 1;
+            let lines = song
+                .directive_lines()
+                .into_iter()
+                .map(|line| line + newline);
+            entry.lines.splice(path_index..path_index, lines);
+        }
+
+        if song.path != old.path {
+            let line = entry.lines.last_mut().unwrap();
+            let ending = line_ending(line).to_string();
+            *line = song.path.clone() + &ending;
+        }
+
+        entry.song = song;
+    }
+}
+
+impl Entry {
+    fn extinf_index(&self) -> Option<usize> {
+        // The last line is the path
+        let directives = &self.lines[..self.lines.len() - 1];
+        directives
+            .iter()
+            .rposition(|line| line.trim().starts_with("#EXTINF:"))
+    }
+
+    /// Replaces the #EXTINF line, or adds one before the other directives
+    fn set_extinf(&mut self, line: String, newline: &str) {
+        match self.extinf_index() {
+            Some(i) => {
+                let ending = line_ending(&self.lines[i]).to_string();
+                self.lines[i] = line + &ending;
+            }
+            None => {
+                let i = self
+                    .lines
+                    .iter()
+                    .position(|line| line.trim().starts_with("#EXT"))
+                    .unwrap_or(self.lines.len() - 1);
+                self.lines.insert(i, line + newline);
+            }
+        }
+    }
+}
+
+/// Directives stored in the song that aren't #EXTINF
+fn is_other_directive(line: &str) -> bool {
+    let line = line.trim();
+    line.starts_with("#EXT") && !line.starts_with("#EXTINF:") && !line.starts_with("#EXTM3U")
+}
+
+fn line_ending(line: &str) -> &str {
+    let content = line.trim_end_matches(['\r', '\n']);
+    &line[content.len()..]
+}
+
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines = std::iter::once(&self.header)
+            .chain(self.entries.iter().flat_map(|e| &e.lines))
+            .chain(std::iter::once(&self.trailer))
+            .filter(|line| !line.is_empty());
+
+        // Only the last line of the file can be missing its line ending, but after editing it
+        // may not be the last one anymore
+        let mut needs_newline = false;
+        for line in lines {
+            if needs_newline {
+                f.write_str(self.newline)?;
+            }
+            f.write_str(line)?;
+            needs_newline = !line.ends_with('\n');
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const PLAYLIST: &str = "#EXTM3U\r\n\
+        #EXTINF:12.5 tvg-logo=\"a, b.png\",First\r\n\
+        #EXTFOO:kept\r\n\
+        /music/first.mp3\r\n\
+        \r\n\
+        #EXTINF:-1,Second\r\n\
+        http://radio.example/stream\r\n\
+        third.flac";
+
+    #[test]
+    fn test_roundtrip() {
+        let doc = Document::parse(PLAYLIST).unwrap();
+        assert_eq!(doc.len(), 3);
+        assert_eq!(doc.get(2).unwrap().path, "third.flac");
+        assert_eq!(doc.to_string(), PLAYLIST);
+
+        let commented = "#EXTM3U\n# Side A\na.mp3\n# The end\n";
+        let doc = Document::parse(commented).unwrap();
+        assert_eq!(doc.len(), 1);
+        assert_eq!(doc.get(0).unwrap().path, "a.mp3");
+        assert_eq!(doc.to_string(), commented);
+
+        let empty = Document::parse("").unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(empty.to_string(), "");
+    }
+
+    #[test]
+    fn test_rename_keeps_the_rest() {
+        let mut doc = Document::parse(PLAYLIST).unwrap();
+        doc.update(0, |s| s.title = "Renamed".into());
+        doc.update(2, |s| s.title = "Third".into());
+        assert_eq!(doc.get(0).unwrap().title, "Renamed");
+        assert_eq!(
+            doc.to_string(),
+            PLAYLIST
+                .replace(",First", ",Renamed")
+                .replace("third.flac", "#EXTINF:0,Third\r\nthird.flac")
+        );
+    }
+
+    #[test]
+    fn test_update_duration() {
+        let mut doc = Document::parse(PLAYLIST).unwrap();
+        doc.update(0, |s| s.duration = Duration::from_millis(2250));
+        doc.update(2, |s| s.duration = Duration::from_secs(3));
+        assert_eq!(
+            doc.to_string(),
+            PLAYLIST
+                .replace("#EXTINF:12.5 ", "#EXTINF:2.25 ")
+                .replace("third.flac", "#EXTINF:3,third.flac\r\nthird.flac")
+        );
+    }
+
+    #[test]
+    fn test_remove_and_move() {
+        let mut doc = Document::parse(PLAYLIST).unwrap();
+        doc.move_entry(2, 0);
+        assert_eq!(
+            doc.to_string(),
+            "#EXTM3U\r\n\
+            third.flac\r\n\
+            #EXTINF:12.5 tvg-logo=\"a, b.png\",First\r\n\
+            #EXTFOO:kept\r\n\
+            /music/first.mp3\r\n\
+            \r\n\
+            #EXTINF:-1,Second\r\n\
+            http://radio.example/stream\r\n"
+        );
+
+        assert_eq!(doc.remove(2).unwrap().title, "Second");
+        assert_eq!(doc.remove(5), None);
+        doc.swap(0, 1);
+        assert_eq!(
+            doc.to_string(),
+            "#EXTM3U\r\n\
+            #EXTINF:12.5 tvg-logo=\"a, b.png\",First\r\n\
+            #EXTFOO:kept\r\n\
+            /music/first.mp3\r\n\
+            third.flac"
+        );
+    }
+
+    #[test]
+    fn test_entry_text() {
+        let mut doc = Document::parse(PLAYLIST).unwrap();
+        let text = doc.entry_text(2).unwrap();
+        assert_eq!(text, "third.flac\r\n");
+        let text = doc.entry_text(0).unwrap();
+        doc.remove(0);
+        assert_eq!(doc.insert_text(0, &text).unwrap(), 1);
+        assert_eq!(doc.to_string(), PLAYLIST);
+    }
+
+    #[test]
+    fn test_insert_and_update_directives() {
+        let mut doc = Document::parse("#EXTM3U\nsong.mp3").unwrap();
+        doc.push(Song {
+            title: "New".into(),
+            duration: std::time::Duration::from_secs(3),
+            path: "new.mp3".into(),
+            ..Default::default()
+        });
+        doc.update(1, |s| s.group = Some("Group".into()));
+        assert_eq!(
+            doc.to_string(),
+            "#EXTM3U\nsong.mp3\n#EXTINF:3,New\n#EXTGRP:Group\nnew.mp3\n"
+        );
+
+        let reparsed = Document::parse(&doc.to_string()).unwrap();
+        assert_eq!(reparsed.songs().collect::<Vec<_>>(), doc.songs().collect::<Vec<_>>());
+    }
+}
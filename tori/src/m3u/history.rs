@@ -0,0 +1,315 @@
+//! Undo and redo of the changes made to playlists. The history lives for the whole session, and is
+//! also saved to disk if `persist_undo_history` is set in the config.
+
+use std::{collections::HashMap, fmt, fs, path::PathBuf, sync::Mutex};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    playlist_management::{self, Edit, ModifiedExternally},
+    writer::{self, Conflicts},
+};
+use crate::{config::Config, error::Result};
+
+/// Oldest changes are forgotten after this many
+const MAX_HISTORY: usize = 100;
+
+static HISTORY: Lazy<Mutex<History>> = Lazy::new(|| Mutex::new(History::load()));
+
+/// Something that was done to the playlists
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Change {
+    Edit { playlist: String, edit: Edit },
+    /// Moves a playlist to the trash
+    Trash { playlist: String },
+    /// Moves a playlist back from the trash
+    Restore { playlist: String, trashed: String },
+    RenamePlaylist { from: String, to: String },
+    /// Creates `copy`, with the same songs as `playlist`
+    CopyPlaylist { playlist: String, copy: String },
+    /// Applies several changes, in order. If one of them fails, the ones before it are undone.
+    Batch { changes: Vec<Change> },
+}
+
+impl Change {
+    /// The playlists whose songs this change edits
+    fn edited_playlists(&self) -> Vec<&str> {
+        match self {
+            Change::Edit { playlist, .. } => vec![playlist],
+            Change::Batch { changes } => changes.iter().flat_map(Change::edited_playlists).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Hashes of the playlists this change edits, as they are now
+    fn hashes(&self) -> Result<HashMap<String, u64>> {
+        self.edited_playlists()
+            .into_iter()
+            .map(|playlist| {
+                let hash = writer::current_hash(&Config::playlist_path(playlist))?;
+                Ok((playlist.to_string(), hash))
+            })
+            .collect()
+    }
+
+    /// Applies the change and returns the one that undoes it, or None if nothing changed
+    fn apply(&self, conflicts: Conflicts) -> Result<Option<Change>> {
+        Ok(match self {
+            Change::Edit { playlist, edit } => {
+                playlist_management::edit_playlist(playlist, edit, conflicts)?.map(|inverse| {
+                    Change::Edit {
+                        playlist: playlist.clone(),
+                        edit: inverse,
+                    }
+                })
+            }
+            Change::Trash { playlist } => {
+                let trashed = playlist_management::trash_playlist(playlist)?;
+                Some(Change::Restore {
+                    playlist: playlist.clone(),
+                    trashed,
+                })
+            }
+            Change::Restore { playlist, trashed } => {
+                playlist_management::restore_playlist(playlist, trashed)?;
+                Some(Change::Trash {
+                    playlist: playlist.clone(),
+                })
+            }
+            Change::RenamePlaylist { from, to } => {
+                playlist_management::rename_playlist(from, to)?;
+                Some(Change::RenamePlaylist {
+                    from: to.clone(),
+                    to: from.clone(),
+                })
+            }
+            Change::CopyPlaylist { playlist, copy } => {
+                playlist_management::copy_playlist(playlist, copy)?;
+                Some(Change::Trash {
+                    playlist: copy.clone(),
+                })
+            }
+            Change::Batch { changes } => {
+                let mut inverses = Vec::new();
+                for change in changes {
+                    match change.apply(conflicts) {
+                        Ok(inverse) => inverses.extend(inverse),
+                        Err(e) => {
+                            for inverse in inverses.iter().rev() {
+                                inverse.apply(Conflicts::Overwrite).ok();
+                            }
+                            return Err(with_retry(e, Action::Perform(self.clone())));
+                        }
+                    }
+                }
+                inverses.reverse();
+                (!inverses.is_empty()).then_some(Change::Batch { changes: inverses })
+            }
+        })
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Edit { playlist, edit } => match edit {
+                Edit::Delete { .. } => write!(f, "deleting a song from '{}'", playlist),
+                Edit::Rename { .. } | Edit::Replace { .. } => {
+                    write!(f, "renaming a song in '{}'", playlist)
+                }
+                Edit::Swap { .. } | Edit::Move { .. } => {
+                    write!(f, "moving a song in '{}'", playlist)
+                }
+                Edit::Insert { .. } => write!(f, "adding a song to '{}'", playlist),
+                Edit::Append { .. } => write!(f, "adding songs to '{}'", playlist),
+                Edit::Truncate { .. } => write!(f, "removing songs from '{}'", playlist),
+                Edit::Batch { edits } if edits.iter().all(|e| matches!(e, Edit::Delete { .. })) => {
+                    write!(f, "removing songs from '{}'", playlist)
+                }
+                Edit::Batch { edits } if edits.iter().all(|e| matches!(e, Edit::Rename { .. })) => {
+                    write!(f, "renaming songs in '{}'", playlist)
+                }
+                Edit::Batch { .. } => write!(f, "editing '{}'", playlist),
+            },
+            Change::Trash { playlist } => write!(f, "deleting '{}'", playlist),
+            Change::Restore { playlist, .. } => write!(f, "restoring '{}'", playlist),
+            Change::RenamePlaylist { from, to } => write!(f, "renaming '{}' to '{}'", from, to),
+            Change::CopyPlaylist { playlist, copy } => {
+                write!(f, "copying '{}' to '{}'", playlist, copy)
+            }
+            Change::Batch { changes } => {
+                for (i, change) in changes.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " and ")?;
+                    }
+                    write!(f, "{}", change)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// What to run again if a playlist was modified externally and the user wants to overwrite it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Perform(Change),
+    Undo,
+    Redo,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Step {
+    change: Change,
+    /// Undoes `change`
+    inverse: Change,
+    /// Hashes of the playlists edited by `change`, from right before it was made (or right after
+    /// it was undone). Edits refer to songs by their index, so they're only redone on playlists
+    /// that still match.
+    before: HashMap<String, u64>,
+    /// Same, from right after `change` was made, for undoing it
+    after: HashMap<String, u64>,
+}
+
+impl Step {
+    fn new(change: Change, inverse: Change, before: HashMap<String, u64>) -> Result<Self> {
+        let after = change.hashes()?;
+        Ok(Step {
+            change,
+            inverse,
+            before,
+            after,
+        })
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct History {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+}
+
+impl History {
+    fn path() -> Option<PathBuf> {
+        Config::global()
+            .persist_undo_history
+            .then(Config::state_dir)
+            .flatten()
+            .map(|dir| dir.join("undo_history.json"))
+    }
+
+    fn load() -> Self {
+        History::path()
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(path) = History::path() {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, serde_json::to_vec(self)?)?;
+        }
+        Ok(())
+    }
+
+    fn push_undo(&mut self, step: Step) {
+        self.undo.push(step);
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.remove(0);
+        }
+    }
+}
+
+/// Applies a change made by the user, so it can be undone later
+pub fn perform(change: Change, conflicts: Conflicts) -> Result<()> {
+    let mut history = HISTORY.lock().unwrap();
+    let before = change.hashes()?;
+    if let Some(inverse) = change.apply(conflicts)? {
+        history.push_undo(Step::new(change, inverse, before)?);
+        history.redo.clear();
+        history.save()?;
+    }
+    Ok(())
+}
+
+/// Undoes the last change. Returns it, or None if there's nothing to undo.
+pub fn undo(conflicts: Conflicts) -> Result<Option<Change>> {
+    let mut history = HISTORY.lock().unwrap();
+    let mut step = match history.undo.last() {
+        Some(step) => step.clone(),
+        None => return Ok(None),
+    };
+
+    check(&step.after, conflicts, Action::Undo)?;
+    step.inverse
+        .apply(conflicts)
+        .map_err(|e| with_retry(e, Action::Undo))?;
+    step.before = step.change.hashes()?;
+    history.undo.pop();
+    history.redo.push(step.clone());
+    history.save()?;
+    Ok(Some(step.change))
+}
+
+/// Redoes the last undone change. Returns it, or None if there's nothing to redo.
+pub fn redo(conflicts: Conflicts) -> Result<Option<Change>> {
+    let mut history = HISTORY.lock().unwrap();
+    let step = match history.redo.last() {
+        Some(step) => step.clone(),
+        None => return Ok(None),
+    };
+
+    check(&step.before, conflicts, Action::Redo)?;
+    let inverse = step
+        .change
+        .apply(conflicts)
+        .map_err(|e| with_retry(e, Action::Redo))?;
+    history.redo.pop();
+    if let Some(inverse) = inverse {
+        history.push_undo(Step::new(step.change.clone(), inverse, step.before)?);
+    }
+    history.save()?;
+    Ok(Some(step.change))
+}
+
+/// Runs an action. Returns the change that was undone or redone, if any.
+pub fn run(action: &Action, conflicts: Conflicts) -> Result<Option<Change>> {
+    match action {
+        Action::Perform(change) => perform(change.clone(), conflicts).map(|()| None),
+        Action::Undo => undo(conflicts),
+        Action::Redo => redo(conflicts),
+    }
+}
+
+/// Fails with [ModifiedExternally] if a playlist no longer has the content it had when a step was
+/// recorded (it may have been edited since, even before tori was restarted), unless `conflicts` is
+/// [Conflicts::Overwrite]
+fn check(hashes: &HashMap<String, u64>, conflicts: Conflicts, retry: Action) -> Result<()> {
+    if conflicts == Conflicts::Overwrite {
+        return Ok(());
+    }
+    for (playlist, &hash) in hashes {
+        if !writer::matches(&Config::playlist_path(playlist), hash)? {
+            return Err(Box::new(ModifiedExternally {
+                playlist: playlist.clone(),
+                retry,
+            }));
+        }
+    }
+    Ok(())
+}
+
+/// Makes a [ModifiedExternally] error retry `action` instead of the change that failed
+fn with_retry(err: crate::error::Error, action: Action) -> crate::error::Error {
+    match err.downcast::<ModifiedExternally>() {
+        Ok(mut conflict) => {
+            conflict.retry = action;
+            conflict
+        }
+        Err(err) => err,
+    }
+}
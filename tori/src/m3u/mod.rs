@@ -1,8 +1,6 @@
-use std::io::{self, ErrorKind, Read, Seek, Write};
-
 use std::time::Duration;
 
-use crate::{config::Config, error::Result};
+use crate::error::Result;
 
 pub mod stringreader;
 pub use stringreader::StringReader;
@@ -10,13 +8,32 @@ pub use stringreader::StringReader;
 pub mod parser;
 pub use parser::Parser;
 
+pub mod document;
+pub use document::Document;
+
+pub mod history;
+
 pub mod playlist_management;
 
+pub mod writer;
+
 #[derive(Debug, Default, Clone, PartialEq)]
 pub struct Song {
     pub title: String,
     pub duration: Duration,
     pub path: String,
+    /// `key="value"` attributes of the #EXTINF line, used by IPTV playlists (e.g. `tvg-logo`)
+    pub attributes: Vec<(String, String)>,
+    /// #EXTGRP
+    pub group: Option<String>,
+    /// #EXTALB
+    pub album: Option<String>,
+    /// #EXTART
+    pub artist: Option<String>,
+    /// #EXTVLCOPT, one per line
+    pub vlc_options: Vec<String>,
+    /// Directives tori doesn't know about, as whole lines. They're written back as they are.
+    pub extra: Vec<String>,
 }
 
 impl Song {
@@ -59,6 +76,7 @@ impl Song {
             title,
             duration,
             path: url.into(),
+            ..Default::default()
         })
     }
 
@@ -107,61 +125,51 @@ impl Song {
             title,
             duration,
             path: path.into(),
+            ..Default::default()
         })
     }
 
     pub fn serialize(&self) -> String {
-        let duration = self.duration.as_secs();
-        format!("#EXTINF:{},{}\n{}\n", duration, self.title, self.path)
+        let mut s = self.extinf_line();
+        s.push('\n');
+        for line in self.directive_lines() {
+            s += &line;
+            s.push('\n');
+        }
+        s += &self.path;
+        s.push('\n');
+        s
     }
 
-    pub fn add_to_playlist(&self, playlist_name: &str) -> Result<()> {
-        let path = Config::playlist_path(playlist_name);
-        let mut file = std::fs::OpenOptions::new()
-            .create(true)
-            .read(true)
-            .append(true)
-            .open(path)?;
-
-        // Write a #EXTM3U header if it doesn't exist
-        file.rewind()?;
-        let mut buf = vec![0u8; "#EXTM3U".len()];
-        let has_extm3u = match file.read_exact(&mut buf) {
-            Ok(()) if buf == b"#EXTM3U" => true,
-            Ok(()) => false,
-            Err(e) if e.kind() == ErrorKind::UnexpectedEof => false,
-            Err(e) => {
-                return Err(Box::new(e));
-            }
-        };
-
-        if !has_extm3u {
-            let mut buf = Vec::new();
-            file.rewind()?;
-            file.read_to_end(&mut buf)?;
-            file.set_len(0)?;
-            file.write_all(b"#EXTM3U\n")?;
-            file.write_all(&buf)?;
+    /// The #EXTINF line, without a line ending
+    fn extinf_line(&self) -> String {
+        // Whole seconds are written without a fraction
+        let duration = self.duration.as_secs_f64();
+        let mut s = format!("#EXTINF:{}", duration);
+        for (key, value) in &self.attributes {
+            s += &format!(" {}=\"{}\"", key, value);
         }
+        s += &format!(",{}", self.title);
+        s
+    }
 
-        // Check if the file ends in a newline
-        file.seek(io::SeekFrom::End(-1))?;
-        buf = vec![0u8];
-        let ends_with_newline = match file.read_exact(&mut buf) {
-            Ok(()) if buf == b"\n" => true,
-            Ok(()) => false,
-            Err(e) if e.kind() == ErrorKind::UnexpectedEof => false,
-            Err(e) => {
-                return Err(Box::new(e));
+    /// Every directive except #EXTINF, one per line, without line endings
+    fn directive_lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let directives = [
+            ("#EXTGRP", &self.group),
+            ("#EXTALB", &self.album),
+            ("#EXTART", &self.artist),
+        ];
+        for (directive, value) in directives {
+            if let Some(value) = value {
+                lines.push(format!("{}:{}", directive, value));
             }
-        };
-
-        if !ends_with_newline {
-            file.write_all(b"\n")?;
         }
-
-        // Write the serialized song
-        file.write_all(self.serialize().as_bytes())?;
-        Ok(())
+        for option in &self.vlc_options {
+            lines.push(format!("#EXTVLCOPT:{}", option));
+        }
+        lines.extend(self.extra.iter().cloned());
+        lines
     }
 }
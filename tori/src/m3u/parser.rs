@@ -14,7 +14,6 @@ use super::StringReader;
 #[derive(Debug)]
 pub enum ParserError {
     Io(io::Error),
-    UnknownExtline(String),
 }
 
 impl Error for ParserError {}
@@ -29,20 +28,79 @@ impl std::fmt::Display for ParserError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ParserError::Io(e) => write!(f, "IO error: {}", e),
-            ParserError::UnknownExtline(s) => write!(f, "Unknown EXT directive: {}", s),
         }
     }
 }
 
 pub type Result<T> = std::result::Result<T, ParserError>;
 
+///////////////////////////
+//        Warning        //
+///////////////////////////
+/// A problem in the playlist that doesn't stop it from being parsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    /// 1-indexed line number
+    pub line: usize,
+    pub kind: WarningKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WarningKind {
+    /// The directive is kept verbatim in [Song::extra]
+    UnknownDirective(String),
+    /// The duration of an #EXTINF line isn't a number. The song's duration is set to zero.
+    InvalidDuration(String),
+    /// The playlist ends with directives that aren't followed by a song path
+    MissingPath,
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            WarningKind::UnknownDirective(s) => {
+                write!(f, "line {}: unknown directive {}", self.line, s)
+            }
+            WarningKind::InvalidDuration(s) => {
+                write!(f, "line {}: invalid duration '{}'", self.line, s)
+            }
+            WarningKind::MissingPath => write!(
+                f,
+                "line {}: the playlist ends without a song path",
+                self.line
+            ),
+        }
+    }
+}
+
 //////////////////////////////////
 //        Ext Directives        //
 //////////////////////////////////
 #[derive(Debug, PartialEq)]
 enum Ext {
     Extm3u,
-    Extinf(Duration, String),
+    Extinf(Extinf),
+    /// #EXTGRP:group
+    Extgrp(String),
+    /// #EXTALB:album
+    Extalb(String),
+    /// #EXTART:artist
+    Extart(String),
+    /// #EXTVLCOPT:option=value
+    Extvlcopt(String),
+    Unknown(String),
+}
+
+/// A directive's prefix, and how to make an [Ext] from the value after it
+type TypedDirective = (&'static str, fn(String) -> Ext);
+
+/// `#EXTINF:duration key="value" ...,title`
+#[derive(Debug, PartialEq)]
+struct Extinf {
+    /// The unparsed duration if it isn't a number
+    duration: std::result::Result<Duration, String>,
+    attributes: Vec<(String, String)>,
+    title: String,
 }
 
 //////////////////////////////
@@ -67,15 +125,14 @@ pub struct Parser<L: LineReader> {
     reader: L,
     line_buf: Option<String>,
     cursor: usize,
+    /// Number of lines read so far
+    line: usize,
+    warnings: Vec<Warning>,
 }
 
 impl Parser<BufReader<fs::File>> {
     pub fn from_file(reader: fs::File) -> Self {
-        Self {
-            reader: BufReader::new(reader),
-            line_buf: None,
-            cursor: 0,
-        }
+        Self::with_reader(BufReader::new(reader))
     }
 
     pub fn from_path(path: impl AsRef<std::path::Path>) -> Result<Self> {
@@ -86,29 +143,44 @@ impl Parser<BufReader<fs::File>> {
 
 impl<'s> Parser<StringReader<'s>> {
     pub fn from_string(s: &'s str) -> Self {
-        Self {
-            reader: StringReader::new(s),
-            line_buf: None,
-            cursor: 0,
-        }
+        Self::with_reader(StringReader::new(s))
     }
 }
 
 impl<R: Read> Parser<BufReader<R>> {
     pub fn from_reader(reader: R) -> Self {
+        Self::with_reader(BufReader::new(reader))
+    }
+}
+
+impl<L: LineReader> Parser<L> {
+    fn with_reader(reader: L) -> Self {
         Self {
-            reader: BufReader::new(reader),
+            reader,
             line_buf: None,
             cursor: 0,
+            line: 0,
+            warnings: Vec::new(),
         }
     }
-}
 
-impl<L: LineReader> Parser<L> {
     pub fn cursor(&self) -> usize {
         self.cursor
     }
 
+    /// Problems found so far. Parsing goes on after them, so they're only worth showing to the
+    /// user.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    fn warn(&mut self, kind: WarningKind) {
+        self.warnings.push(Warning {
+            line: self.line,
+            kind,
+        });
+    }
+
     fn peek_line(&mut self) -> Result<Option<&str>> {
         if self.line_buf.is_none() {
             let (mut line, bytes) = self.reader.next_line()?;
@@ -117,6 +189,7 @@ impl<L: LineReader> Parser<L> {
             }
 
             self.cursor += bytes;
+            self.line += 1;
 
             let is_nl = |c| c == Some(b'\n') || c == Some(b'\r');
             while is_nl(line.as_bytes().last().copied()) {
@@ -150,13 +223,27 @@ impl<L: LineReader> Parser<L> {
             if line.is_empty() {
             } else if line.starts_with("#EXT") {
                 use Ext::*;
-                match parse_extline(line)? {
+                match parse_extline(line) {
                     Extm3u => {}
-                    Extinf(d, t) => {
-                        song.duration = d;
-                        song.title = t;
+                    Extinf(extinf) => {
+                        song.duration = extinf.duration.unwrap_or_else(|d| {
+                            self.warn(WarningKind::InvalidDuration(d));
+                            Duration::default()
+                        });
+                        song.attributes = extinf.attributes;
+                        song.title = extinf.title;
+                    }
+                    Extgrp(group) => song.group = Some(group),
+                    Extalb(album) => song.album = Some(album),
+                    Extart(artist) => song.artist = Some(artist),
+                    Extvlcopt(option) => song.vlc_options.push(option),
+                    Unknown(directive) => {
+                        self.warn(WarningKind::UnknownDirective(directive.clone()));
+                        song.extra.push(directive);
                     }
                 }
+            } else if line.starts_with('#') {
+                // A comment. Document keeps it with the lines of the song that follows.
             } else {
                 song.path = line.into();
                 if song.title.is_empty() {
@@ -165,6 +252,10 @@ impl<L: LineReader> Parser<L> {
                 return Ok(Some(song));
             }
         }
+
+        if song != Song::default() {
+            self.warn(WarningKind::MissingPath);
+        }
         Ok(None)
     }
 
@@ -177,25 +268,98 @@ impl<L: LineReader> Parser<L> {
     }
 }
 
-fn parse_extline(line: &str) -> Result<Ext> {
+fn parse_extline(line: &str) -> Ext {
     use Ext::*;
     if line.starts_with("#EXTM3U") {
-        return Ok(Extm3u);
+        return Extm3u;
     }
 
     if let Some(line) = line.strip_prefix("#EXTINF:") {
-        let mut parts = line.splitn(2, ',');
-        let duration = Duration::from_secs(
-            parts
-                .next()
-                .and_then(|p| p.parse::<f64>().ok())
-                .unwrap_or_default() as u64,
-        );
-        let title = parts.next().unwrap_or_default().to_string();
-        return Ok(Extinf(duration, title));
+        return Extinf(parse_extinf(line));
+    }
+
+    let typed: [TypedDirective; 4] = [
+        ("#EXTGRP:", Extgrp),
+        ("#EXTALB:", Extalb),
+        ("#EXTART:", Extart),
+        ("#EXTVLCOPT:", Extvlcopt),
+    ];
+    for (prefix, ext) in typed {
+        if let Some(value) = line.strip_prefix(prefix) {
+            return ext(value.trim().to_string());
+        }
+    }
+
+    Unknown(line.to_string())
+}
+
+/// Parses what comes after `#EXTINF:`. IPTV playlists put `key="value"` attributes between the
+/// duration and the title, and the quoted values may contain commas.
+fn parse_extinf(s: &str) -> Extinf {
+    let duration_end = s
+        .find(|c: char| c == ',' || c.is_whitespace())
+        .unwrap_or(s.len());
+    let (duration, rest) = s.split_at(duration_end);
+    let duration = match duration.trim() {
+        "" => Ok(Duration::default()),
+        d => d
+            .parse::<f64>()
+            .ok()
+            .filter(|d| d.is_finite())
+            // Live streams have a duration of -1
+            .map(|d| Duration::from_secs_f64(d.max(0.0)))
+            .ok_or_else(|| d.to_string()),
+    };
+
+    let (attributes, title) = match title_comma(rest) {
+        Some(i) => (&rest[..i], &rest[i + 1..]),
+        None => (rest, ""),
+    };
+
+    Extinf {
+        duration,
+        attributes: parse_attributes(attributes),
+        title: title.to_string(),
     }
+}
 
-    Err(ParserError::UnknownExtline(line.to_string()))
+/// Finds the comma that separates the title in an #EXTINF line, skipping the ones inside quoted
+/// attribute values
+pub(super) fn title_comma(s: &str) -> Option<usize> {
+    let mut in_quotes = false;
+    s.find(|c: char| {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        }
+        c == ',' && !in_quotes
+    })
+}
+
+/// Parses `key="value" key2=value2`
+fn parse_attributes(mut s: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    loop {
+        s = s.trim_start();
+        let eq = match s.find('=') {
+            Some(eq) => eq,
+            None => break,
+        };
+        let key = s[..eq].trim().to_string();
+        s = &s[eq + 1..];
+
+        let value = if let Some(quoted) = s.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            s = quoted.get(end + 1..).unwrap_or_default();
+            &quoted[..end]
+        } else {
+            let end = s.find(char::is_whitespace).unwrap_or(s.len());
+            let value = &s[..end];
+            s = &s[end..];
+            value
+        };
+        attributes.push((key, value.to_string()));
+    }
+    attributes
 }
 
 #[cfg(test)]
@@ -204,18 +368,53 @@ mod tests {
 
     #[test]
     fn test_extline_parsing() {
-        assert_eq!(parse_extline("#EXTM3U").ok(), Some(Ext::Extm3u));
+        assert_eq!(parse_extline("#EXTM3U"), Ext::Extm3u);
+        assert_eq!(
+            parse_extline("#EXTINF:10,Artist - Title"),
+            Ext::Extinf(Extinf {
+                duration: Ok(Duration::from_secs_f64(10.)),
+                attributes: vec![],
+                title: "Artist - Title".into()
+            })
+        );
+        assert_eq!(
+            parse_extline("#EXTINF:"),
+            Ext::Extinf(Extinf {
+                duration: Ok(Duration::default()),
+                attributes: vec![],
+                title: String::default()
+            })
+        );
         assert_eq!(
-            parse_extline("#EXTINF:10,Artist - Title").ok(),
-            Some(Ext::Extinf(
-                Duration::from_secs_f64(10.),
-                "Artist - Title".into()
-            ))
+            parse_extline("#EXTINF:abc,Title"),
+            Ext::Extinf(Extinf {
+                duration: Err("abc".into()),
+                attributes: vec![],
+                title: "Title".into()
+            })
+        );
+        assert_eq!(parse_extline("#EXTGRP: Rock"), Ext::Extgrp("Rock".into()));
+        assert_eq!(
+            parse_extline("#EXTVLCOPT:network-caching=1000"),
+            Ext::Extvlcopt("network-caching=1000".into())
+        );
+    }
+
+    #[test]
+    fn test_extinf_attributes() {
+        let extinf = parse_extinf(
+            r#"-1 tvg-id="news.1" tvg-name="News, Weather" group-title=News,News Channel"#,
         );
+        assert_eq!(extinf.duration, Ok(Duration::default()));
         assert_eq!(
-            parse_extline("#EXTINF:").ok(),
-            Some(Ext::Extinf(Duration::default(), String::default()))
+            extinf.attributes,
+            vec![
+                ("tvg-id".into(), "news.1".into()),
+                ("tvg-name".into(), "News, Weather".into()),
+                ("group-title".into(), "News".into()),
+            ]
         );
+        assert_eq!(extinf.title, "News Channel");
     }
 
     #[test]
@@ -238,12 +437,14 @@ mod tests {
                 Song {
                     title: "Artist - Title".into(),
                     duration: Duration::from_secs_f64(10.),
-                    path: "https://www.youtube.com/watch?v=dQw4w9WgXcQ".into()
+                    path: "https://www.youtube.com/watch?v=dQw4w9WgXcQ".into(),
+                    ..Default::default()
                 },
                 Song {
                     title: "Yup".into(),
                     duration: Duration::from_secs_f64(0.),
-                    path: "/path/to/local/song".into()
+                    path: "/path/to/local/song".into(),
+                    ..Default::default()
                 }
             ]),
         );
@@ -258,16 +459,11 @@ mod tests {
 
         assert_eq!(
             parser.all_songs().ok(),
-            Some(vec![
-                Song {
-                    title: "#DOESNOTBEGINWITHEXT".into(),
-                    duration: Duration::default(),
-                    path: "#DOESNOTBEGINWITHEXT".into(),
-                },
-                Song {
+            Some(vec![Song {
                     title: "something.mp3".into(),
                     duration: Duration::default(),
-                    path: "something.mp3".into()
+                    path: "something.mp3".into(),
+                    ..Default::default()
                 },
             ]),
         );
@@ -282,13 +478,55 @@ mod tests {
             #EXTINF:10,Artist - Title
             https://www.youtube.com/watch?v=dQw4w9WgXcQ
             #EXTNOTSUPPORTED
+            #EXTGRP:Group
+            #EXTVLCOPT:start-time=10
             /path/to/local/song
+            #EXTINF:ten,Nope
             "#,
         );
 
-        assert!(matches!(
-            parser.all_songs(),
-            Err(ParserError::UnknownExtline(s)) if s == "#EXTNOTSUPPORTED"
-        ));
+        let songs = parser.all_songs().unwrap();
+        assert_eq!(songs.len(), 2);
+        assert_eq!(songs[1].extra, vec!["#EXTNOTSUPPORTED".to_string()]);
+        assert_eq!(songs[1].group.as_deref(), Some("Group"));
+        assert_eq!(songs[1].vlc_options, vec!["start-time=10".to_string()]);
+
+        let kinds: Vec<_> = parser.warnings().iter().map(|w| &w.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &WarningKind::UnknownDirective("#EXTNOTSUPPORTED".into()),
+                &WarningKind::InvalidDuration("ten".into()),
+                &WarningKind::MissingPath,
+            ]
+        );
+        assert_eq!(parser.warnings()[0].line, 6);
+    }
+
+    #[test]
+    fn test_serialize_roundtrip() {
+        let mut parser = Parser::from_string(
+            r#"#EXTINF:5 tvg-logo="logo.png",Radio
+#EXTGRP:Radios
+#EXTVLCOPT:network-caching=1000
+#EXTFOO:bar
+http://radio.example/stream
+"#,
+        );
+        let song = parser.next_song().unwrap().unwrap();
+        let serialized = song.serialize();
+        assert_eq!(
+            serialized,
+            r#"#EXTINF:5 tvg-logo="logo.png",Radio
+#EXTGRP:Radios
+#EXTVLCOPT:network-caching=1000
+#EXTFOO:bar
+http://radio.example/stream
+"#
+        );
+        assert_eq!(
+            Parser::from_string(&serialized).next_song().unwrap(),
+            Some(song)
+        );
     }
 }
@@ -1,11 +1,28 @@
 use std::{
-    fs,
-    io::{self, Write},
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::PathBuf,
     result::Result as StdResult,
+    sync::Mutex,
     thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use crate::{app::App, config::Config, error::Result, events::Event, m3u};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app::App,
+    config::Config,
+    error::Result,
+    events::Event,
+    m3u::{
+        self,
+        history::{self, Action, Change},
+        writer::{self, Conflicts, Outcome},
+    },
+    player::Player,
+};
 
 /// Adds a song to an existing playlist
 pub fn add_song(app: &mut App, playlist: &str, song_path: String) {
@@ -19,46 +36,65 @@ pub fn add_song(app: &mut App, playlist: &str, song_path: String) {
     let sender = app.channel.sender.clone();
     let playlist = playlist.to_string();
     thread::spawn(move || {
-        add_song_recursively(&song_path, &playlist);
-
-        // Extract last part (separated by '/') of the song_path
-        let mut rsplit = song_path.trim_end_matches('/').rsplit('/');
-        let song = rsplit.next().unwrap_or(&song_path).to_string();
-
-        let event = Event::SongAdded { playlist, song };
+        let event = match add_songs(&song_path, &playlist) {
+            Ok(playlist) => {
+                // Extract last part (separated by '/') of the song_path
+                let mut rsplit = song_path.trim_end_matches('/').rsplit('/');
+                let song = rsplit.next().unwrap_or(&song_path).to_string();
+                Event::SongAdded { playlist, song }
+            }
+            Err(e) => Event::Error(format!(
+                "Failed to add '{}' to playlist. Error: {}",
+                song_path, e
+            )),
+        };
         sender.send(event).expect("Failed to send internal event");
     });
 }
 
-/// Adds songs from some path. If the path points to a directory, it'll traverse the directory
-/// recursively, adding all songs inside it. If the path points to a file, it'll add that file.
-/// If it points to a URL, it adds the url.
+/// Adds the songs at `path` to a playlist, as a single change so adding a whole directory is
+/// undone at once. Returns the name of the playlist, which may have been renamed meanwhile.
+fn add_songs(path: &str, playlist_name: &str) -> Result<String> {
+    let mut songs = Vec::new();
+    add_song_recursively(path, &mut songs)?;
+    let playlist = current_name(playlist_name);
+
+    let change = Change::Edit {
+        playlist: playlist.clone(),
+        edit: Edit::Append {
+            text: songs.iter().map(m3u::Song::serialize).collect(),
+        },
+    };
+    history::perform(change, Conflicts::Overwrite)?;
+    Ok(playlist)
+}
+
+/// Collects songs from some path. If the path points to a directory, it'll traverse the directory
+/// recursively, collecting all songs inside it. If the path points to a file, it'll collect that
+/// file. If it points to a URL, it collects the url.
 /// We do not traverse symlinks, to avoid infinite loops.
-fn add_song_recursively(path: &str, playlist_name: &str) {
+fn add_song_recursively(path: &str, songs: &mut Vec<m3u::Song>) -> Result<()> {
     let file = std::path::Path::new(&path);
     if file.is_dir() && !file.is_symlink() {
-        let mut entries: Vec<_> = fs::read_dir(path)
-            .unwrap_or_else(|e| panic!("Failed to read directory '{}'. Error: {}", path, e))
-            .map(|entry| entry.expect("Failed to read entry").path())
-            .collect();
+        let mut entries = fs::read_dir(path)
+            .map_err(|e| format!("Failed to read directory '{}'. Error: {}", path, e))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
 
         entries.sort();
 
         for path in entries {
-            let path = path.to_str().unwrap_or_else(|| {
-                panic!(
-                    "Failed to add '{}' to playlist. Path is not valid UTF-8",
-                    path.display()
-                )
-            });
-            add_song_recursively(path, playlist_name);
+            let path = path
+                .to_str()
+                .ok_or_else(|| format!("Path '{}' is not valid UTF-8", path.display()))?;
+            add_song_recursively(path, songs)?;
         }
     } else if !image_file(file) {
         let song = m3u::Song::from_path(path)
-            .unwrap_or_else(|e| panic!("Failed to add '{}' to playlist. Error: {}", path, e));
-        song.add_to_playlist(playlist_name)
-            .unwrap_or_else(|e| panic!("Failed to add '{}' to playlist. Error: {}", path, e));
+            .map_err(|e| format!("Failed to read '{}'. Error: {}", path, e))?;
+        songs.push(song);
     }
+    Ok(())
 }
 
 fn surely_invalid_path(path: &str) -> bool {
@@ -81,6 +117,8 @@ fn image_file(file: &std::path::Path) -> bool {
 pub enum CreatePlaylistError {
     PlaylistAlreadyExists,
     InvalidChar(char),
+    /// Files that start with a dot are hidden, like the trash
+    StartsWithDot,
     IOError(io::Error),
 }
 
@@ -90,111 +128,411 @@ impl From<io::Error> for CreatePlaylistError {
     }
 }
 
-/// Creates the corresponding .m3u8 file for a new playlist
-pub fn create_playlist(playlist_name: &str) -> StdResult<(), CreatePlaylistError> {
+impl std::error::Error for CreatePlaylistError {}
+
+impl std::fmt::Display for CreatePlaylistError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PlaylistAlreadyExists => write!(f, "A playlist with this name already exists!"),
+            Self::InvalidChar(c) => write!(f, "Playlist names cannot contain '{}'", c),
+            Self::StartsWithDot => write!(f, "Playlist names cannot start with '.'"),
+            Self::IOError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Checks that a new playlist can be called `playlist_name`
+fn validate_new_name(playlist_name: &str) -> StdResult<(), CreatePlaylistError> {
     if playlist_name.contains('/') {
         return Err(CreatePlaylistError::InvalidChar('/'));
     }
     if playlist_name.contains('\\') {
         return Err(CreatePlaylistError::InvalidChar('\\'));
     }
+    if playlist_name.starts_with('.') {
+        return Err(CreatePlaylistError::StartsWithDot);
+    }
+    if Config::playlist_path(playlist_name).try_exists()? {
+        return Err(CreatePlaylistError::PlaylistAlreadyExists);
+    }
+    Ok(())
+}
 
-    let path = Config::playlist_path(playlist_name);
-
-    // TODO: when it's stabilized, use std::fs::File::create_new
-    if path.try_exists()? {
-        Err(CreatePlaylistError::PlaylistAlreadyExists)
-    } else {
-        fs::File::create(path)?;
-        Ok(())
+/// Names of the playlists in `playlists_dir`, sorted
+pub fn list_playlists() -> Result<Vec<String>> {
+    let dir = fs::read_dir(&Config::global().playlists_dir)
+        .map_err(|e| format!("Failed to read playlists directory: {}", e))?;
+
+    let mut playlists = Vec::new();
+    for entry in dir {
+        let file_name = entry?
+            .file_name()
+            .into_string()
+            .map_err(|filename| format!("File '{:?}' has invalid UTF-8", filename))?;
+        // Hidden files are tori's lock and temporary files, and the trash
+        if !file_name.starts_with('.') {
+            playlists.push(file_name.trim_end_matches(".m3u8").to_string());
+        }
     }
+
+    playlists.sort();
+    Ok(playlists)
 }
 
-pub fn delete_song(playlist_name: &str, index: usize) -> Result<()> {
-    let path = Config::playlist_path(playlist_name);
-    let content = fs::read_to_string(&path)?;
-    let mut parser = m3u::Parser::from_string(&content);
+/// Creates the corresponding .m3u8 file for a new playlist
+pub fn create_playlist(playlist_name: &str) -> StdResult<(), CreatePlaylistError> {
+    // TODO: when it's stabilized, use std::fs::File::create_new
+    validate_new_name(playlist_name)?;
+    fs::File::create(Config::playlist_path(playlist_name))?;
+    Ok(())
+}
 
-    parser.next_header()?;
-    for _ in 0..index {
-        parser.next_song()?;
+/// Playlists renamed in this session, from the old name to the new one
+static RENAMED: Lazy<Mutex<HashMap<String, String>>> = Lazy::new(Default::default);
+
+/// The name a playlist has now, following any renames since `playlist_name` was read. Used by
+/// work that runs in the background, like adding songs.
+pub fn current_name(playlist_name: &str) -> String {
+    let renamed = RENAMED.lock().unwrap();
+    let mut name = playlist_name;
+    // A playlist can be renamed back to an old name, so stop if we've gone in a circle
+    for _ in 0..renamed.len() {
+        match renamed.get(name) {
+            Some(new_name) if new_name != playlist_name => name = new_name,
+            _ => break,
+        }
     }
+    name.to_string()
+}
 
-    let start_pos = parser.cursor();
-    let _song = parser.next_song()?;
-    let end_pos = parser.cursor();
+pub fn rename_playlist(from: &str, to: &str) -> Result<()> {
+    validate_new_name(to)?;
+    fs::rename(Config::playlist_path(from), Config::playlist_path(to))?;
 
-    let mut file = fs::OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .open(&path)?;
-    file.write_all(content[..start_pos].as_bytes())?;
-    file.write_all(content[end_pos..].as_bytes())?;
+    let mut renamed = RENAMED.lock().unwrap();
+    renamed.remove(to);
+    renamed.insert(from.to_string(), to.to_string());
+    Ok(())
+}
 
+/// Creates the playlist `copy` with the same content as `playlist`
+pub fn copy_playlist(playlist: &str, copy: &str) -> Result<()> {
+    validate_new_name(copy)?;
+    fs::copy(Config::playlist_path(playlist), Config::playlist_path(copy))?;
     Ok(())
 }
 
-pub fn rename_song(playlist_name: &str, index: usize, new_name: &str) -> Result<()> {
-    let path = Config::playlist_path(playlist_name);
-    let content = fs::read_to_string(&path)?;
-    let mut parser = m3u::Parser::from_string(&content);
+/// The entries of a playlist, as they're written in its file
+pub fn playlist_text(playlist: &str) -> Result<String> {
+    let doc = m3u::Document::open(Config::playlist_path(playlist))?;
+    Ok((0..doc.len()).filter_map(|i| doc.entry_text(i)).collect())
+}
 
-    parser.next_header()?;
-    for _ in 0..index {
-        parser.next_song()?;
-    }
+/// The entries at `indices` of a playlist, in that order, as they're written in its file
+pub fn songs_text(playlist: &str, indices: &[usize]) -> Result<String> {
+    let doc = m3u::Document::open(Config::playlist_path(playlist))?;
+    Ok(indices.iter().filter_map(|&i| doc.entry_text(i)).collect())
+}
 
-    let start_pos = parser.cursor();
-    let song = parser.next_song()?;
-    let end_pos = parser.cursor();
+/// The paths of every song in a playlist
+pub fn song_paths(playlist: &str) -> Result<HashSet<String>> {
+    let doc = m3u::Document::open(Config::playlist_path(playlist))?;
+    Ok(doc.songs().map(|song| song.path.clone()).collect())
+}
 
-    if let Some(mut song) = song {
-        song.title = new_name.to_string();
+/// A change to the songs of a playlist
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Edit {
+    Delete { index: usize },
+    Rename { index: usize, title: String },
+    /// Swaps the `index`-th song with the `index+1`-th (0-indexed)
+    Swap { index: usize },
+    /// Moves the song at `from` so it ends up at index `to`
+    Move { from: usize, to: usize },
+    /// Inserts entries, as they're written in the file, at `index`
+    Insert { index: usize, text: String },
+    /// Replaces the entry at `index` with the one in `text`
+    Replace { index: usize, text: String },
+    /// Adds entries, as they're written in the file, to the end of the playlist
+    Append { text: String },
+    /// Removes every entry from `len` onwards
+    Truncate { len: usize },
+    /// Applies several edits, in order, in a single write
+    Batch { edits: Vec<Edit> },
+}
 
-        let mut file = fs::OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(&path)?;
-        file.write_all(content[..start_pos].as_bytes())?;
-        file.write_all(song.serialize().as_bytes())?;
-        file.write_all(content[end_pos..].as_bytes())?;
+impl Edit {
+    /// Applies the edit and returns the one that undoes it, or None if nothing changed (e.g. the
+    /// index is out of bounds)
+    fn apply(&self, doc: &mut m3u::Document) -> Option<Edit> {
+        match self {
+            Edit::Delete { index } => {
+                let text = doc.entry_text(*index)?;
+                doc.remove(*index);
+                Some(Edit::Insert {
+                    index: *index,
+                    text,
+                })
+            }
+            Edit::Rename { index, title } => {
+                let text = doc.entry_text(*index)?;
+                doc.update(*index, |song| song.title = title.clone());
+                Some(Edit::Replace {
+                    index: *index,
+                    text,
+                })
+            }
+            Edit::Swap { index } => {
+                if index + 1 < doc.len() {
+                    doc.swap(*index, index + 1);
+                    Some(Edit::Swap { index: *index })
+                } else {
+                    None
+                }
+            }
+            Edit::Move { from, to } => {
+                if from == to || *from >= doc.len() || *to >= doc.len() {
+                    return None;
+                }
+                doc.move_entry(*from, *to);
+                Some(Edit::Move {
+                    from: *to,
+                    to: *from,
+                })
+            }
+            Edit::Insert { index, text } => {
+                if *index > doc.len() {
+                    return None;
+                }
+                // The entry came from tori itself, so it's always a single song
+                match doc.insert_text(*index, text).ok()? {
+                    0 => None,
+                    _ => Some(Edit::Delete { index: *index }),
+                }
+            }
+            Edit::Append { text } => {
+                doc.add_header();
+                let len = doc.len();
+                match doc.insert_text(len, text).ok()? {
+                    0 => None,
+                    _ => Some(Edit::Truncate { len }),
+                }
+            }
+            Edit::Truncate { len } => {
+                if *len >= doc.len() {
+                    return None;
+                }
+                let text = (*len..doc.len()).filter_map(|i| doc.entry_text(i)).collect();
+                while doc.len() > *len {
+                    doc.remove(*len);
+                }
+                Some(Edit::Append { text })
+            }
+            Edit::Batch { edits } => {
+                let mut inverses: Vec<_> = edits.iter().filter_map(|e| e.apply(doc)).collect();
+                if inverses.is_empty() {
+                    return None;
+                }
+                inverses.reverse();
+                Some(Edit::Batch { edits: inverses })
+            }
+            Edit::Replace { index, text } => {
+                let old_text = doc.entry_text(*index)?;
+                doc.remove(*index);
+                doc.insert_text(*index, text).ok()?;
+                Some(Edit::Replace {
+                    index: *index,
+                    text: old_text,
+                })
+            }
+        }
     }
+}
 
-    Ok(())
+/// The playlist was changed outside of tori (probably in `$EDITOR`) since it was last loaded, so
+/// the edit wasn't applied
+#[derive(Debug)]
+pub struct ModifiedExternally {
+    pub playlist: String,
+    /// What to do if the user wants to overwrite the playlist anyway
+    pub retry: Action,
 }
 
-/// Swaps `index`-th song with the `index+1`-th (0-indexed)
-pub fn swap_song(playlist_name: &str, index: usize) -> Result<()> {
+impl std::error::Error for ModifiedExternally {}
+
+impl std::fmt::Display for ModifiedExternally {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "'{}' was changed outside of tori", self.playlist)
+    }
+}
+
+/// Applies an edit to a playlist file and returns the edit that undoes it. Fails with
+/// [ModifiedExternally] if the file changed since it was last loaded, unless `conflicts` is
+/// [Conflicts::Overwrite].
+///
+/// Edits made by the user should go through [super::history::perform], so they can be undone.
+pub fn edit_playlist(
+    playlist_name: &str,
+    edit: &Edit,
+    conflicts: Conflicts,
+) -> Result<Option<Edit>> {
     let path = Config::playlist_path(playlist_name);
-    let content = fs::read_to_string(&path)?;
-    let mut parser = m3u::Parser::from_string(&content);
+    // Appending can't lose any changes made outside of tori
+    let conflicts = match edit {
+        Edit::Append { .. } => Conflicts::Overwrite,
+        _ => conflicts,
+    };
+    let mut inverse = None;
+    match writer::write(&path, conflicts, |doc| inverse = edit.apply(doc))? {
+        Outcome::Written => Ok(inverse),
+        Outcome::ModifiedExternally => Err(Box::new(ModifiedExternally {
+            playlist: playlist_name.to_string(),
+            retry: Action::Perform(Change::Edit {
+                playlist: playlist_name.to_string(),
+                edit: edit.clone(),
+            }),
+        })),
+    }
+}
 
-    parser.next_header()?;
-    for _ in 0..index {
-        parser.next_song()?;
+/// Adds the songs in the player's queue to the end of a playlist, creating it if it doesn't exist.
+/// Returns how many songs were added.
+pub fn save_queue(player: &impl Player, playlist_name: &str) -> Result<usize> {
+    match create_playlist(playlist_name) {
+        Ok(()) | Err(CreatePlaylistError::PlaylistAlreadyExists) => {}
+        Err(e) => return Err(e.into()),
     }
 
-    let start_pos = parser.cursor();
-    let song1 = parser.next_song()?;
-    let song2 = parser.next_song()?;
-    let end_pos = parser.cursor();
+    let count = player.playlist_count()?;
+    let mut text = String::new();
+    for i in 0..count {
+        let path = player.playlist_track_path(i)?;
+        // Asking yt-dlp about every URL would take too long, so only local files get a duration
+        let duration = if std::path::Path::new(&path).is_file() {
+            m3u::Song::parse_local_file(&path)
+                .map(|song| song.duration)
+                .unwrap_or_default()
+        } else {
+            Duration::ZERO
+        };
+        let song = m3u::Song {
+            title: player.playlist_track_title(i)?,
+            duration,
+            path,
+            ..Default::default()
+        };
+        text += &song.serialize();
+    }
 
-    if let (Some(song1), Some(song2)) = (song1, song2) {
-        let mut file = fs::OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(&path)?;
-        file.write_all(content[..start_pos].as_bytes())?;
-        file.write_all(song2.serialize().as_bytes())?;
-        file.write_all(song1.serialize().as_bytes())?;
-        file.write_all(content[end_pos..].as_bytes())?;
+    if count > 0 {
+        let change = Change::Edit {
+            playlist: playlist_name.to_string(),
+            edit: Edit::Append { text },
+        };
+        history::perform(change, Conflicts::Check)?;
     }
+    Ok(count)
+}
 
-    Ok(())
+/// Replaces the player's queue with the songs of a playlist, and starts playing the first one.
+/// Returns how many songs were queued.
+pub fn play_playlist(player: &mut impl Player, playlist_name: &str) -> Result<usize> {
+    let doc = m3u::Document::open(Config::playlist_path(playlist_name))?;
+    let mut songs = doc.songs();
+    match songs.next() {
+        Some(first) => player.play(&first.path)?,
+        None => return Err(format!("'{}' doesn't have any songs", playlist_name).into()),
+    }
+    for song in songs {
+        player.queue(&song.path)?;
+    }
+    Ok(doc.len())
+}
+
+/// Deleted playlists are moved here, so they can be restored
+fn trash_dir() -> PathBuf {
+    PathBuf::from(&Config::global().playlists_dir).join(".trash")
+}
+
+/// Moves a playlist to the trash and returns its file name there
+pub fn trash_playlist(playlist_name: &str) -> Result<String> {
+    let dir = trash_dir();
+    fs::create_dir_all(&dir)?;
+
+    // The same playlist name may be trashed more than once
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut trashed = format!("{}.{}.m3u8", playlist_name, timestamp);
+    let mut n = 1;
+    while dir.join(&trashed).try_exists()? {
+        trashed = format!("{}.{}-{}.m3u8", playlist_name, timestamp, n);
+        n += 1;
+    }
+
+    fs::rename(Config::playlist_path(playlist_name), dir.join(&trashed))?;
+    Ok(trashed)
 }
 
-pub fn delete_playlist(playlist_name: &str) -> Result<()> {
+/// Moves a playlist back from the trash
+pub fn restore_playlist(playlist_name: &str, trashed: &str) -> Result<()> {
     let path = Config::playlist_path(playlist_name);
-    fs::remove_file(path)?;
+    if path.try_exists()? {
+        return Err(format!(
+            "Can't restore '{}', there's another playlist with the same name",
+            playlist_name
+        )
+        .into());
+    }
+    fs::rename(trash_dir().join(trashed), path)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edits_undo() {
+        let original = "#EXTM3U\n#EXTINF:1.5,A\na.mp3\n\n# comment\nb.mp3\n#EXTINF:3,C\nc.mp3\n";
+        let edits = [
+            Edit::Delete { index: 1 },
+            Edit::Rename {
+                index: 1,
+                title: "B".into(),
+            },
+            Edit::Swap { index: 0 },
+            Edit::Move { from: 2, to: 0 },
+            Edit::Move { from: 0, to: 2 },
+            Edit::Insert {
+                index: 3,
+                text: "#EXTINF:4,D\nd.mp3\n".into(),
+            },
+            Edit::Append {
+                text: "d.mp3\ne.mp3\n".into(),
+            },
+            Edit::Truncate { len: 1 },
+            Edit::Batch {
+                edits: vec![
+                    Edit::Delete { index: 2 },
+                    Edit::Swap { index: 0 },
+                    Edit::Delete { index: 5 },
+                ],
+            },
+        ];
+
+        for edit in edits {
+            let mut doc = m3u::Document::parse(original).unwrap();
+            let inverse = edit.apply(&mut doc).unwrap();
+            assert_ne!(doc.to_string(), original);
+            inverse.apply(&mut doc).unwrap();
+            assert_eq!(doc.to_string(), original, "undoing {:?}", edit);
+        }
+
+        let mut doc = m3u::Document::parse(original).unwrap();
+        assert_eq!(Edit::Delete { index: 5 }.apply(&mut doc), None);
+        assert_eq!(Edit::Swap { index: 3 }.apply(&mut doc), None);
+        assert_eq!(Edit::Move { from: 1, to: 4 }.apply(&mut doc), None);
+    }
+}
@@ -0,0 +1,218 @@
+//! Every change to a playlist file goes through [write]. Writes are serialized by a mutex (for the
+//! threads of this process) and an advisory lock on a file in the playlists directory (for other
+//! tori instances). The new content is written to a temporary file that's renamed over the
+//! playlist, so a crash never leaves a half-written playlist behind.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fs,
+    hash::{Hash, Hasher},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use fs2::FileExt;
+use once_cell::sync::Lazy;
+
+use super::Document;
+use crate::error::Result;
+
+/// Hashes of the playlists' contents, as tori last read or wrote them
+static KNOWN: Lazy<Mutex<HashMap<PathBuf, u64>>> = Lazy::new(Default::default);
+
+const LOCK_FILE: &str = ".tori.lock";
+
+/// What to do when a playlist was changed outside of tori since it was last read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflicts {
+    /// Don't write anything, and return [Outcome::ModifiedExternally]. A playlist that tori hasn't
+    /// read (see [remember]) counts as changed, unless it's empty or doesn't exist.
+    Check,
+    /// Apply the change to the file as it is now
+    Overwrite,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Written,
+    ModifiedExternally,
+}
+
+/// Remembers the content of a playlist that was just read, so we can tell if it's changed by
+/// someone else before we write to it
+pub fn remember(path: &Path, content: &str) {
+    KNOWN
+        .lock()
+        .unwrap()
+        .insert(path.to_path_buf(), hash(content));
+}
+
+/// Hash of the playlist at `path` as it is now, to check it later with [matches]
+pub fn current_hash(path: &Path) -> Result<u64> {
+    Ok(hash(&read(path)?))
+}
+
+/// Whether the playlist at `path` still has the content hashed by [current_hash]. If it does, it's
+/// remembered as if it was just read.
+pub fn matches(path: &Path, expected: u64) -> Result<bool> {
+    let mut known = KNOWN.lock().unwrap();
+    let matches = current_hash(path)? == expected;
+    if matches {
+        known.insert(path.to_path_buf(), expected);
+    }
+    Ok(matches)
+}
+
+/// Whether the playlist at `path` is still the way tori last read or wrote it
+pub fn is_unchanged(path: &Path) -> Result<bool> {
+    let known = KNOWN.lock().unwrap();
+    let content = read(path)?;
+    Ok(is_up_to_date(known.get(path).copied(), &content))
+}
+
+/// Reads the playlist at `path` (or an empty one, if it doesn't exist), lets `f` change it and
+/// writes it back
+pub fn write(path: &Path, conflicts: Conflicts, f: impl FnOnce(&mut Document)) -> Result<Outcome> {
+    let mut known = KNOWN.lock().unwrap();
+    let _lock = lock_dir(path)?;
+
+    let content = read(path)?;
+    let known_hash = known.get(path).copied();
+    let up_to_date = is_up_to_date(known_hash, &content);
+    if !up_to_date && conflicts == Conflicts::Check {
+        return Ok(Outcome::ModifiedExternally);
+    }
+
+    let mut doc = Document::parse(&content)?;
+    f(&mut doc);
+    let new_content = doc.to_string();
+    if new_content != content {
+        write_atomically(path, &new_content)?;
+    }
+
+    // Otherwise, whoever shows this playlist still has to reload it and see the external changes
+    if up_to_date || known_hash.is_none() {
+        known.insert(path.to_path_buf(), hash(&new_content));
+    }
+    Ok(Outcome::Written)
+}
+
+fn is_up_to_date(known_hash: Option<u64>, content: &str) -> bool {
+    match known_hash {
+        Some(h) => h == hash(content),
+        // A playlist tori hasn't read can't be checked, so it only counts as up to date if it's
+        // empty. Otherwise indices into it would be guesses.
+        None => content.is_empty(),
+    }
+}
+
+fn read(path: &Path) -> io::Result<String> {
+    match fs::read_to_string(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        res => res,
+    }
+}
+
+/// Takes the lock of the directory that contains `path`. It's released when the file is dropped.
+fn lock_dir(path: &Path) -> io::Result<fs::File> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join(LOCK_FILE))?;
+    file.lock_exclusive()?;
+    Ok(file)
+}
+
+fn write_atomically(path: &Path, content: &str) -> io::Result<()> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp_path = path.with_file_name(format!(".{}.tmp", file_name));
+
+    let res = (|| {
+        let mut tmp = fs::File::create(&tmp_path)?;
+        tmp.write_all(content.as_bytes())?;
+        tmp.sync_all()?;
+        fs::rename(&tmp_path, path)
+    })();
+
+    if res.is_err() {
+        fs::remove_file(&tmp_path).ok();
+    }
+    res
+}
+
+fn hash(content: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_external_changes() {
+        let dir = std::env::temp_dir().join(format!("tori-writer-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("playlist.m3u8");
+        let content = "#EXTM3U\n#EXTINF:1,A\na.mp3\n#EXTINF:2,B\nb.mp3\n";
+        fs::write(&path, content).unwrap();
+        remember(&path, content);
+
+        let swap = |doc: &mut Document| doc.swap(0, 1);
+        assert_eq!(
+            write(&path, Conflicts::Check, swap).unwrap(),
+            Outcome::Written
+        );
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "#EXTM3U\n#EXTINF:2,B\nb.mp3\n#EXTINF:1,A\na.mp3\n"
+        );
+
+        // Changed in $EDITOR
+        fs::write(&path, "#EXTM3U\nc.mp3\n").unwrap();
+        assert_eq!(
+            write(&path, Conflicts::Check, |doc| {
+                doc.remove(0);
+            })
+            .unwrap(),
+            Outcome::ModifiedExternally
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "#EXTM3U\nc.mp3\n");
+
+        assert_eq!(
+            write(&path, Conflicts::Overwrite, |doc| {
+                doc.remove(0);
+            })
+            .unwrap(),
+            Outcome::Written
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "#EXTM3U\n");
+        assert!(!dir.join(".playlist.m3u8.tmp").exists());
+
+        // Never read by tori, so it might not be what the user expects
+        let unread = dir.join("unread.m3u8");
+        fs::write(&unread, content).unwrap();
+        assert_eq!(
+            write(&unread, Conflicts::Check, swap).unwrap(),
+            Outcome::ModifiedExternally
+        );
+        assert!(!is_unchanged(&unread).unwrap());
+        assert!(!matches(&unread, hash("#EXTM3U\n")).unwrap());
+        assert!(matches(&unread, current_hash(&unread).unwrap()).unwrap());
+        assert!(is_unchanged(&unread).unwrap());
+        assert_eq!(
+            write(&unread, Conflicts::Check, swap).unwrap(),
+            Outcome::Written
+        );
+        assert_eq!(
+            write(&dir.join("new.m3u8"), Conflicts::Check, |doc| doc.add_header()).unwrap(),
+            Outcome::Written
+        );
+
+        fs::remove_dir_all(dir).ok();
+    }
+}
@@ -1,4 +1,8 @@
-use crate::error::Result;
+use crate::error::{Error, Result};
+use crate::visualizer::SampleSource;
+
+pub mod order;
+use order::Repeat;
 
 #[cfg(feature = "mpv")]
 mod mpv;
@@ -19,8 +23,8 @@ pub trait Player: Sized {
     fn playlist_next(&mut self) -> Result<()>;
     fn playlist_previous(&mut self) -> Result<()>;
     fn toggle_pause(&mut self) -> Result<()>;
-    fn toggle_loop_file(&mut self) -> Result<()>;
-    fn looping_file(&self) -> Result<bool>;
+    fn set_repeat(&mut self, repeat: Repeat) -> Result<()>;
+    fn repeat(&self) -> Result<Repeat>;
     fn volume(&self) -> Result<i64>;
     fn add_volume(&mut self, x: isize) -> Result<()>;
     fn set_volume(&mut self, x: i64) -> Result<()>;
@@ -31,10 +35,35 @@ pub trait Player: Sized {
     fn time_pos(&self) -> Result<i64>;
     fn time_remaining(&self) -> Result<i64>;
     fn paused(&self) -> Result<bool>;
-    fn shuffle(&mut self) -> Result<()>;
+
+    /// Returns an error that happened in the background since the last call, like a track that
+    /// failed to play. Players that report every error synchronously don't need to override this.
+    fn take_error(&mut self) -> Option<Error> {
+        None
+    }
+
+    // Audio devices:
+    fn output_devices(&self) -> Result<Vec<String>>;
+    fn set_output_device(&mut self, device: &str) -> Result<()>;
+
+    /// The samples that are being played, so the visualizer can analyze them without cava.
+    /// `None` if the player doesn't have access to them.
+    fn sample_source(&self) -> Option<Box<dyn SampleSource>> {
+        None
+    }
 
     // Playlist-related:
     fn playlist_count(&self) -> Result<usize>;
     fn playlist_track_title(&self, i: usize) -> Result<String>;
+    fn playlist_track_path(&self, i: usize) -> Result<String>;
     fn playlist_position(&self) -> Result<usize>;
+    /// Inserts a song right after the current one, or plays it if nothing is playing
+    fn play_next(&mut self, path: &str) -> Result<()>;
+    /// Removes the `i`-th song. If it's the current one, the next one starts playing.
+    fn playlist_remove(&mut self, i: usize) -> Result<()>;
+    /// Moves the `i`-th song so it ends up at index `j`
+    fn playlist_move(&mut self, i: usize, j: usize) -> Result<()>;
+    /// Removes every song except the current one
+    fn playlist_clear(&mut self) -> Result<()>;
+    fn playlist_play_index(&mut self, i: usize) -> Result<()>;
 }
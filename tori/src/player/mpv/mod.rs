@@ -1,4 +1,5 @@
-use crate::config::Config;
+use super::order::Repeat;
+use crate::config::{Config, ReplayGain};
 use crate::error::Result;
 
 mod select;
@@ -17,6 +18,12 @@ impl super::Player for MpvPlayer {
             if let Some(ao) = &Config::global().mpv_ao {
                 mpv.set_str("ao", ao.as_str())?;
             }
+            let replaygain = match Config::global().replaygain {
+                ReplayGain::Track => "track",
+                ReplayGain::Album => "album",
+                ReplayGain::Off => "no",
+            };
+            mpv.set_str("replaygain", replaygain)?;
             Ok(())
         })?;
 
@@ -65,19 +72,25 @@ impl super::Player for MpvPlayer {
         Ok(())
     }
 
-    fn toggle_loop_file(&mut self) -> Result<()> {
-        let status = self.mpv.get_str("loop-file");
-        let next_status = match status.as_deref() {
-            Ok("no") => "inf",
-            _ => "no",
+    fn set_repeat(&mut self, repeat: Repeat) -> Result<()> {
+        let (loop_file, loop_playlist) = match repeat {
+            Repeat::Off => ("no", "no"),
+            Repeat::All => ("no", "inf"),
+            Repeat::One => ("inf", "no"),
         };
-        self.mpv.set_str("loop-file", next_status)?;
+        self.mpv.set_str("loop-file", loop_file)?;
+        self.mpv.set_str("loop-playlist", loop_playlist)?;
         Ok(())
     }
 
-    fn looping_file(&self) -> Result<bool> {
-        let status = self.mpv.get_str("loop-file")?;
-        Ok(status == "inf")
+    fn repeat(&self) -> Result<Repeat> {
+        Ok(if self.mpv.get_str("loop-file")? != "no" {
+            Repeat::One
+        } else if self.mpv.get_str("loop-playlist")? != "no" {
+            Repeat::All
+        } else {
+            Repeat::Off
+        })
     }
 
     fn volume(&self) -> Result<i64> {
@@ -123,8 +136,16 @@ impl super::Player for MpvPlayer {
         Ok(self.mpv.get_bool("pause")?)
     }
 
-    fn shuffle(&mut self) -> Result<()> {
-        Ok(self.mpv.command("playlist-shuffle", &[])?)
+    fn output_devices(&self) -> Result<Vec<String>> {
+        let count = self.mpv.get_i64("audio-device-list/count")?;
+        (0..count)
+            .map(|i| Ok(self.mpv.get_str(&format!("audio-device-list/{}/name", i))?))
+            .collect()
+    }
+
+    fn set_output_device(&mut self, device: &str) -> Result<()> {
+        self.mpv.set_str("audio-device", device)?;
+        Ok(())
     }
 
     fn playlist_count(&self) -> Result<usize> {
@@ -138,7 +159,47 @@ impl super::Player for MpvPlayer {
             .or_else(|_| self.mpv.get_str(&format!("playlist/{}/filename", i)))?)
     }
 
+    fn playlist_track_path(&self, i: usize) -> Result<String> {
+        Ok(self.mpv.get_str(&format!("playlist/{}/filename", i))?)
+    }
+
     fn playlist_position(&self) -> Result<usize> {
-        Ok(self.mpv.get_i64("playlist-playing-pos")? as usize)
+        // -1 when nothing is playing
+        usize::try_from(self.mpv.get_i64("playlist-playing-pos")?)
+            .map_err(|_| "Nothing is playing".into())
+    }
+
+    fn play_next(&mut self, path: &str) -> Result<()> {
+        // loadfile's insert-next flag needs mpv 0.37, so append and move it instead
+        let position = self.playlist_position();
+        self.mpv.queue(path)?;
+        if let Ok(i) = position {
+            let last = self.playlist_count()? - 1;
+            self.playlist_move(last, i + 1)?;
+        }
+        Ok(())
+    }
+
+    fn playlist_remove(&mut self, i: usize) -> Result<()> {
+        self.mpv.command("playlist-remove", &[&i.to_string()])?;
+        Ok(())
+    }
+
+    fn playlist_move(&mut self, i: usize, j: usize) -> Result<()> {
+        // mpv moves the entry before the one at the second index
+        let before = if i < j { j + 1 } else { j };
+        self.mpv
+            .command("playlist-move", &[&i.to_string(), &before.to_string()])?;
+        Ok(())
+    }
+
+    fn playlist_clear(&mut self) -> Result<()> {
+        self.mpv.command("playlist-clear", &[])?;
+        Ok(())
+    }
+
+    fn playlist_play_index(&mut self, i: usize) -> Result<()> {
+        self.mpv.command("playlist-play-index", &[&i.to_string()])?;
+        Ok(())
     }
 }
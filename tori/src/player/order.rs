@@ -0,0 +1,311 @@
+//! The order the queue is played in. Repeating is left to the players, but shuffling is done here,
+//! by moving the songs of the queue around, so it works the same with every player.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use rand::seq::SliceRandom;
+
+use super::Player;
+use crate::error::Result;
+
+/// What plays after the current song ends
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Repeat {
+    /// The queue stops after the last song
+    #[default]
+    Off,
+    /// The queue starts again after the last song
+    All,
+    /// The current song plays again
+    One,
+}
+
+impl Repeat {
+    pub fn next(self) -> Self {
+        match self {
+            Repeat::Off => Repeat::All,
+            Repeat::All => Repeat::One,
+            Repeat::One => Repeat::Off,
+        }
+    }
+}
+
+/// Shuffles the queue, remembering the order it had before
+#[derive(Debug, Default)]
+pub struct Shuffle {
+    /// The queue before it was shuffled, or None if it isn't shuffled
+    original: Option<Vec<String>>,
+}
+
+impl Shuffle {
+    /// A shuffle that was turned on before, like in a previous session
+    pub fn restored(original: Option<Vec<String>>) -> Self {
+        Shuffle { original }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.original.is_some()
+    }
+
+    /// The queue before it was shuffled, or None if it isn't shuffled
+    pub fn original(&self) -> Option<&[String]> {
+        self.original.as_deref()
+    }
+
+    pub fn toggle(&mut self, player: &mut impl Player) -> Result<()> {
+        if self.is_on() {
+            self.unshuffle(player)
+        } else {
+            self.shuffle(player)
+        }
+    }
+
+    /// Shuffles the songs after the current one. The ones that already played stay before it, so
+    /// no song plays again until all of them have.
+    pub fn shuffle(&mut self, player: &mut impl Player) -> Result<()> {
+        let queue = queue_paths(player)?;
+        let first_unplayed = player.playlist_position().map_or(0, |i| i + 1);
+        let mut shuffled = queue.clone();
+        if first_unplayed < shuffled.len() {
+            shuffled[first_unplayed..].shuffle(&mut rand::thread_rng());
+        }
+
+        reorder(player, &queue, &shuffled)?;
+        self.original = Some(queue);
+        Ok(())
+    }
+
+    /// Puts the songs back in the order they had before shuffling. The current song keeps playing.
+    pub fn unshuffle(&mut self, player: &mut impl Player) -> Result<()> {
+        if let Some(original) = self.original.take() {
+            let queue = queue_paths(player)?;
+            let target = unshuffled(&queue, &original);
+            reorder(player, &queue, &target)?;
+        }
+        Ok(())
+    }
+}
+
+fn queue_paths(player: &impl Player) -> Result<Vec<String>> {
+    (0..player.playlist_count()?)
+        .map(|i| player.playlist_track_path(i))
+        .collect()
+}
+
+/// The songs of `queue`, in the order they have in `original`. Songs that weren't in `original`
+/// (because they were queued after shuffling) go at the end.
+fn unshuffled(queue: &[String], original: &[String]) -> Vec<String> {
+    let mut left: Vec<Option<&String>> = queue.iter().map(Some).collect();
+    let mut ordered = Vec::with_capacity(queue.len());
+    for path in original {
+        // The same song can be queued more than once
+        if let Some(slot) = left.iter_mut().find(|s| *s == &Some(path)) {
+            ordered.extend(slot.take().cloned());
+        }
+    }
+    ordered.extend(left.into_iter().flatten().cloned());
+    ordered
+}
+
+/// The moves (from, to) that turn `queue` into `target`, which has the same songs. Each move
+/// removes the song at `from` and inserts it at `to`. Only the songs that aren't already in the
+/// right order relative to each other are moved, and each of them just once, since every move is a
+/// command to the player.
+fn moves(queue: &[String], target: &[String]) -> Vec<(usize, usize)> {
+    // Where each song of the queue goes. Copies of a song keep their order.
+    let mut positions: HashMap<&str, VecDeque<usize>> = HashMap::new();
+    for (i, path) in target.iter().enumerate() {
+        positions.entry(path).or_default().push_back(i);
+    }
+    let mut order: Vec<usize> = queue
+        .iter()
+        .filter_map(|path| positions.get_mut(path.as_str())?.pop_front())
+        .collect();
+
+    let stay = longest_increasing(&order);
+    let mut moves = Vec::new();
+    for t in 0..order.len() {
+        if stay.contains(&t) {
+            continue;
+        }
+        // The songs before `t` are already in place, so it goes right after `t - 1`
+        let from = order.iter().position(|&o| o == t).unwrap();
+        order.remove(from);
+        let to = match t {
+            0 => 0,
+            _ => order.iter().position(|&o| o == t - 1).unwrap() + 1,
+        };
+        order.insert(to, t);
+        moves.push((from, to));
+    }
+    moves
+}
+
+/// The values of the longest increasing subsequence of `values`
+fn longest_increasing(values: &[usize]) -> HashSet<usize> {
+    // `tails[k]` is the index of the smallest value that ends an increasing subsequence of length
+    // `k + 1`, and `previous[i]` the index of the value before `values[i]` in its subsequence
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous = vec![None; values.len()];
+    for (i, &value) in values.iter().enumerate() {
+        let k = tails.partition_point(|&j| values[j] < value);
+        previous[i] = k.checked_sub(1).map(|k| tails[k]);
+        if k == tails.len() {
+            tails.push(i);
+        } else {
+            tails[k] = i;
+        }
+    }
+
+    let mut longest = HashSet::new();
+    let mut i = tails.last().copied();
+    while let Some(j) = i {
+        longest.insert(values[j]);
+        i = previous[j];
+    }
+    longest
+}
+
+fn reorder(player: &mut impl Player, queue: &[String], target: &[String]) -> Result<()> {
+    for (from, to) in moves(queue, target) {
+        player.playlist_move(from, to)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(s: &[&str]) -> Vec<String> {
+        s.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn queue_of(target: &[String]) -> Vec<String> {
+        let mut queue = target.to_vec();
+        queue.sort();
+        queue
+    }
+
+    /// Moves the songs of `queue` like the player would
+    fn apply(queue: &[String], target: &[String]) -> Vec<String> {
+        let mut moved = queue.to_vec();
+        for (from, to) in moves(queue, target) {
+            let song = moved.remove(from);
+            moved.insert(to, song);
+        }
+        moved
+    }
+
+    #[test]
+    fn test_moves() {
+        let queue = strings(&["a", "b", "c", "d", "a"]);
+        let target = strings(&["d", "a", "c", "a", "b"]);
+        assert_eq!(apply(&queue, &target), target);
+
+        let mut target = strings(&["a", "b", "c", "d", "e", "f", "g", "h"]);
+        for _ in 0..100 {
+            target.shuffle(&mut rand::thread_rng());
+            assert_eq!(apply(&queue_of(&target), &target), target);
+        }
+
+        // Moving the first song to the end is a single move
+        let queue = strings(&["a", "b", "c", "d", "e"]);
+        let target = strings(&["b", "c", "d", "e", "a"]);
+        assert_eq!(moves(&queue, &target), [(0, 4)]);
+        assert_eq!(moves(&queue, &queue), []);
+    }
+
+    /// Just a queue, which is all shuffling needs
+    #[derive(Default)]
+    struct FakePlayer {
+        queue: Vec<String>,
+        position: usize,
+    }
+
+    impl Player for FakePlayer {
+        fn new() -> Result<Self> {
+            Ok(FakePlayer::default())
+        }
+        fn play(&mut self, path: &str) -> Result<()> {
+            self.queue = vec![path.to_string()];
+            self.position = 0;
+            Ok(())
+        }
+        fn queue(&mut self, path: &str) -> Result<()> {
+            self.queue.push(path.to_string());
+            Ok(())
+        }
+        fn playlist_count(&self) -> Result<usize> {
+            Ok(self.queue.len())
+        }
+        fn playlist_track_path(&self, i: usize) -> Result<String> {
+            Ok(self.queue[i].clone())
+        }
+        fn playlist_position(&self) -> Result<usize> {
+            Ok(self.position)
+        }
+        fn playlist_move(&mut self, i: usize, j: usize) -> Result<()> {
+            let song = self.queue.remove(i);
+            self.queue.insert(j, song);
+            Ok(())
+        }
+
+        fn seek(&mut self, _: f64) -> Result<()> { unimplemented!() }
+        fn seek_absolute(&mut self, _: usize) -> Result<()> { unimplemented!() }
+        fn playlist_next(&mut self) -> Result<()> { unimplemented!() }
+        fn playlist_previous(&mut self) -> Result<()> { unimplemented!() }
+        fn toggle_pause(&mut self) -> Result<()> { unimplemented!() }
+        fn set_repeat(&mut self, _: Repeat) -> Result<()> { unimplemented!() }
+        fn repeat(&self) -> Result<Repeat> { unimplemented!() }
+        fn volume(&self) -> Result<i64> { unimplemented!() }
+        fn add_volume(&mut self, _: isize) -> Result<()> { unimplemented!() }
+        fn set_volume(&mut self, _: i64) -> Result<()> { unimplemented!() }
+        fn toggle_mute(&mut self) -> Result<()> { unimplemented!() }
+        fn muted(&self) -> Result<bool> { unimplemented!() }
+        fn media_title(&self) -> Result<String> { unimplemented!() }
+        fn percent_pos(&self) -> Result<i64> { unimplemented!() }
+        fn time_pos(&self) -> Result<i64> { unimplemented!() }
+        fn time_remaining(&self) -> Result<i64> { unimplemented!() }
+        fn paused(&self) -> Result<bool> { unimplemented!() }
+        fn output_devices(&self) -> Result<Vec<String>> { unimplemented!() }
+        fn set_output_device(&mut self, _: &str) -> Result<()> { unimplemented!() }
+        fn playlist_track_title(&self, _: usize) -> Result<String> { unimplemented!() }
+        fn play_next(&mut self, _: &str) -> Result<()> { unimplemented!() }
+        fn playlist_remove(&mut self, _: usize) -> Result<()> { unimplemented!() }
+        fn playlist_clear(&mut self) -> Result<()> { unimplemented!() }
+        fn playlist_play_index(&mut self, _: usize) -> Result<()> { unimplemented!() }
+    }
+
+    #[test]
+    fn test_replacing_the_queue_forgets_the_shuffle() {
+        let mut player = FakePlayer::default();
+        for path in ["a", "b", "c", "d", "e", "f"] {
+            player.queue(path).unwrap();
+        }
+        let mut shuffle = Shuffle::default();
+        shuffle.toggle(&mut player).unwrap();
+        assert!(shuffle.is_on());
+
+        // What the app does when a playlist replaces the queue
+        player.play("x").unwrap();
+        player.queue("y").unwrap();
+        player.queue("z").unwrap();
+        shuffle = Shuffle::default();
+
+        // Shuffling the new queue and turning it off again keeps the new queue's order
+        shuffle.toggle(&mut player).unwrap();
+        assert_eq!(shuffle.original(), Some(&strings(&["x", "y", "z"])[..]));
+        shuffle.toggle(&mut player).unwrap();
+        assert!(!shuffle.is_on());
+        assert_eq!(player.queue, strings(&["x", "y", "z"]));
+    }
+
+    #[test]
+    fn test_unshuffled() {
+        let original = strings(&["a", "b", "c", "a"]);
+        // "c" was removed and "e" was queued after shuffling
+        let queue = strings(&["a", "e", "b", "a"]);
+        assert_eq!(unshuffled(&queue, &original), strings(&["a", "b", "a", "e"]));
+    }
+}
@@ -1,14 +1,19 @@
-use tori_player::{Result, controller::Controller};
-
-macro_rules! my_todo {
-    () => {
-        Ok(Default::default())
-    };
-}
+use super::order::Repeat;
+use crate::config::{Config, ReplayGain};
+use crate::visualizer::SampleSource;
+use tori_player::{controller::Controller, ReplayGainMode, Result, SampleTap};
 
 impl super::Player for tori_player::Player {
     fn new() -> Result<Self> {
-        let controller = Controller::default();
+        let config = Config::global();
+        let mut controller = Controller::default();
+        controller.set_output_host(config.cpal_host.clone());
+        controller.set_output_device(config.cpal_device.clone())?;
+        controller.set_replay_gain_mode(match config.replaygain {
+            ReplayGain::Track => ReplayGainMode::Track,
+            ReplayGain::Album => ReplayGainMode::Album,
+            ReplayGain::Off => ReplayGainMode::Off,
+        });
         Ok(Self { controller })
     }
 
@@ -17,90 +22,155 @@ impl super::Player for tori_player::Player {
     }
 
     fn queue(&mut self, path: &str) -> Result<()> {
-        my_todo!()
+        self.controller.queue(path)
     }
 
     fn seek(&mut self, seconds: f64) -> Result<()> {
-        my_todo!()
+        self.controller.seek(seconds)
     }
 
     fn seek_absolute(&mut self, percent: usize) -> Result<()> {
-        my_todo!()
+        self.controller.seek_absolute(percent as f64)
     }
 
     fn playlist_next(&mut self) -> Result<()> {
-        my_todo!()
+        self.controller.playlist_next()
     }
 
     fn playlist_previous(&mut self) -> Result<()> {
-        my_todo!()
+        self.controller.playlist_previous()
     }
 
     fn toggle_pause(&mut self) -> Result<()> {
-        my_todo!()
+        self.controller.toggle_pause()
     }
 
-    fn toggle_loop_file(&mut self) -> Result<()> {
-        my_todo!()
+    fn set_repeat(&mut self, repeat: Repeat) -> Result<()> {
+        self.controller.set_loop_file(repeat == Repeat::One);
+        self.controller.set_loop_playlist(repeat == Repeat::All);
+        Ok(())
     }
 
-    fn looping_file(&self) -> Result<bool> {
-        my_todo!()
+    fn repeat(&self) -> Result<Repeat> {
+        Ok(if self.controller.looping_file() {
+            Repeat::One
+        } else if self.controller.looping_playlist() {
+            Repeat::All
+        } else {
+            Repeat::Off
+        })
     }
 
     fn volume(&self) -> Result<i64> {
-        my_todo!()
+        Ok(self.controller.volume())
     }
 
     fn add_volume(&mut self, x: isize) -> Result<()> {
-        my_todo!()
+        let volume = self.controller.volume() + x as i64;
+        self.controller.set_volume(volume);
+        Ok(())
     }
 
     fn set_volume(&mut self, x: i64) -> Result<()> {
-        my_todo!()
+        self.controller.set_volume(x);
+        Ok(())
     }
 
     fn toggle_mute(&mut self) -> Result<()> {
-        my_todo!()
+        self.controller.toggle_mute();
+        Ok(())
     }
 
     fn muted(&self) -> Result<bool> {
-        my_todo!()
+        Ok(self.controller.muted())
     }
 
     fn media_title(&self) -> Result<String> {
-        my_todo!()
+        Ok(self.controller.media_title())
     }
 
     fn percent_pos(&self) -> Result<i64> {
-        my_todo!()
+        let duration = self.controller.duration();
+        if duration > 0.0 {
+            Ok((100.0 * self.controller.time_pos() / duration) as i64)
+        } else {
+            Ok(0)
+        }
     }
 
     fn time_pos(&self) -> Result<i64> {
-        my_todo!()
+        Ok(self.controller.time_pos() as i64)
     }
 
     fn time_remaining(&self) -> Result<i64> {
-        my_todo!()
+        Ok(self.controller.time_remaining().unwrap_or_default() as i64)
     }
 
     fn paused(&self) -> Result<bool> {
-        my_todo!()
+        Ok(self.controller.paused())
+    }
+
+    fn take_error(&mut self) -> Option<crate::error::Error> {
+        self.controller.take_error().map(Into::into)
     }
 
-    fn shuffle(&mut self) -> Result<()> {
-        my_todo!()
+    fn output_devices(&self) -> Result<Vec<String>> {
+        self.controller.output_devices()
+    }
+
+    fn set_output_device(&mut self, device: &str) -> Result<()> {
+        self.controller.set_output_device(Some(device.to_string()))
+    }
+
+    fn sample_source(&self) -> Option<Box<dyn SampleSource>> {
+        Some(Box::new(self.controller.sample_tap()))
     }
 
     fn playlist_count(&self) -> Result<usize> {
-        my_todo!()
+        Ok(self.controller.playlist_count())
     }
 
     fn playlist_track_title(&self, i: usize) -> Result<String> {
-        my_todo!()
+        self.controller.playlist_track_title(i)
+    }
+
+    fn playlist_track_path(&self, i: usize) -> Result<String> {
+        self.controller.playlist_track_path(i)
     }
 
     fn playlist_position(&self) -> Result<usize> {
-        my_todo!()
+        self.controller
+            .playlist_position()
+            .ok_or_else(|| "Nothing is playing".into())
+    }
+
+    fn play_next(&mut self, path: &str) -> Result<()> {
+        self.controller.play_next(path)
+    }
+
+    fn playlist_remove(&mut self, i: usize) -> Result<()> {
+        self.controller.playlist_remove(i)
+    }
+
+    fn playlist_move(&mut self, i: usize, j: usize) -> Result<()> {
+        self.controller.playlist_move(i, j)
+    }
+
+    fn playlist_clear(&mut self) -> Result<()> {
+        self.controller.playlist_clear()
+    }
+
+    fn playlist_play_index(&mut self, i: usize) -> Result<()> {
+        self.controller.playlist_play_index(i)
+    }
+}
+
+impl SampleSource for SampleTap {
+    fn read(&mut self, buf: &mut [f32]) -> std::io::Result<usize> {
+        Ok(SampleTap::read(self, buf))
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SampleTap::sample_rate(self)
     }
 }
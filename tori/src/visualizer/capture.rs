@@ -0,0 +1,96 @@
+//! Captures what the system is playing from the PulseAudio/PipeWire monitor of the default sink,
+//! for players that don't give us their samples (like mpv)
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read},
+    process::{Child, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use super::{spectrum::FFT_LEN, SampleSource};
+use crate::error::Result;
+
+const SAMPLE_RATE: u32 = 44100;
+
+/// Samples that haven't been read are dropped after this many, so a slow reader doesn't lag behind
+const MAX_PENDING: usize = 4 * FFT_LEN;
+
+pub struct MonitorCapture {
+    process: Child,
+    pending: Arc<Mutex<VecDeque<f32>>>,
+}
+
+impl MonitorCapture {
+    pub fn new() -> Result<Self> {
+        let mut process = std::process::Command::new("parec")
+            .args([
+                "--device=@DEFAULT_MONITOR@",
+                "--format=float32le",
+                "--channels=1",
+                &format!("--rate={}", SAMPLE_RATE),
+                "--raw",
+                "--latency-msec=20",
+            ])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .stdin(Stdio::null())
+            .spawn()
+            .map_err(|e| {
+                format!("Failed to capture the audio with `parec`. Is PulseAudio or pipewire-pulse installed? The received error was: {}", e)
+            })?;
+
+        let pending = Arc::new(Mutex::new(VecDeque::with_capacity(MAX_PENDING)));
+        let mut stdout = process.stdout.take().unwrap();
+        {
+            let pending = pending.clone();
+            thread::spawn(move || {
+                // 1024 samples, about 23ms
+                let mut buf = [0_u8; 4096];
+                while stdout.read_exact(&mut buf).is_ok() {
+                    let mut pending = pending.lock().unwrap();
+                    pending.extend(
+                        buf.chunks_exact(4)
+                            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                    );
+                    let excess = pending.len().saturating_sub(MAX_PENDING);
+                    pending.drain(..excess);
+                }
+            });
+        }
+
+        Ok(Self { process, pending })
+    }
+}
+
+impl SampleSource for MonitorCapture {
+    fn read(&mut self, buf: &mut [f32]) -> io::Result<usize> {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.is_empty() {
+            if let Some(status) = self.process.try_wait()? {
+                return Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    format!("parec exited with {}", status),
+                ));
+            }
+        }
+
+        let n = buf.len().min(pending.len());
+        for (dst, src) in buf.iter_mut().zip(pending.drain(..n)) {
+            *dst = src;
+        }
+        Ok(n)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+}
+
+impl Drop for MonitorCapture {
+    fn drop(&mut self) {
+        self.process.kill().ok();
+        self.process.wait().ok();
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    path::PathBuf,
+    process::{Child, Stdio},
+    result::Result as StdResult,
+};
+
+use rand::{thread_rng, Rng};
+
+use super::{ThreadResult, VisualizerSource};
+use crate::error::Result;
+
+macro_rules! cava_config {
+    () => {
+        r#"
+[general]
+bars = {0}
+framerate = 45
+lower_cutoff_freq = 30
+higher_cutoff_freq = 16000
+
+[output]
+method = raw
+channels = mono
+data_format = binary
+bit_format = 16bit
+reverse = 0
+
+[smoothing]
+; monstercat = 1
+noise_reduction = {1}
+"#
+    };
+}
+
+pub struct CavaOptions {
+    pub bars: usize,
+    pub noise_reduction: f32,
+}
+
+/// Bars computed by an external `cava` process
+pub struct Cava {
+    tmp_path: PathBuf,
+    process: Child,
+    buf: Vec<u8>,
+}
+
+impl Cava {
+    pub fn new(opts: CavaOptions) -> Result<Self> {
+        let tmp_path = tori_tempfile(&opts)?;
+
+        let process = std::process::Command::new("cava")
+            .arg("-p")
+            .arg(&tmp_path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .stdin(Stdio::null())
+            .spawn()
+            .map_err(|e| {
+                std::fs::remove_file(&tmp_path).ok();
+                format!("Failed to spawn the visualizer process. Is `cava` installed? The received error was: {}", e)
+            })?;
+
+        Ok(Self {
+            tmp_path,
+            process,
+            buf: vec![0_u8; 2 * opts.bars],
+        })
+    }
+}
+
+impl VisualizerSource for Cava {
+    fn next_frame(&mut self, bars: &mut [u16]) -> ThreadResult {
+        let stdout = self.process.stdout.as_mut().unwrap();
+        let read_res = stdout.read_exact(&mut self.buf);
+
+        if let Err(e) = read_res {
+            let mut stderr_contents = String::new();
+            let stderr = self.process.stderr.as_mut().unwrap();
+            stderr.read_to_string(&mut stderr_contents).unwrap();
+            return Err(format!("'{}'. Process stderr: {}", e, stderr_contents).into());
+        }
+
+        for (i, bar) in bars.iter_mut().enumerate() {
+            *bar = u16::from_le_bytes([self.buf[2 * i], self.buf[2 * i + 1]]);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Cava {
+    fn drop(&mut self) {
+        self.process.kill().ok();
+        self.process.wait().ok();
+        std::fs::remove_file(&self.tmp_path).ok();
+    }
+}
+
+fn tori_tempfile(opts: &CavaOptions) -> StdResult<PathBuf, std::io::Error> {
+    let path = std::env::temp_dir().join(format!("tori-{:x}", thread_rng().gen::<u32>()));
+
+    if path.is_file() {
+        return Err(io::ErrorKind::AlreadyExists.into());
+    }
+
+    let cava_config = format!(cava_config!(), opts.bars, opts.noise_reduction);
+
+    let mut temp = File::create(&path)?;
+    temp.write_all(cava_config.as_bytes()).unwrap();
+    temp.flush().unwrap();
+
+    Ok(path)
+}
@@ -1,10 +1,6 @@
 use std::{
     error::Error as StdError,
-    fs::File,
-    io::{self, Read, Write},
-    mem,
-    path::PathBuf,
-    process::Stdio,
+    io, mem,
     result::Result as StdResult,
     sync::{
         atomic::{self, AtomicBool},
@@ -13,41 +9,43 @@ use std::{
     thread,
 };
 
-use rand::{thread_rng, Rng};
 use tui::{
     layout::Rect,
     style::{Color, Style},
 };
 
-use crate::{config::Config, error::Result};
-
-macro_rules! cava_config {
-    () => {
-        r#"
-[general]
-bars = {0}
-framerate = 45
-lower_cutoff_freq = 30
-higher_cutoff_freq = 16000
-
-[output]
-method = raw
-channels = mono
-data_format = binary
-bit_format = 16bit
-reverse = 0
-
-[smoothing]
-; monstercat = 1
-noise_reduction = 0.3
-"#
-    };
-}
+use crate::{
+    config::{Config, VisualizerBackend},
+    error::Result,
+};
+
+mod capture;
+use capture::MonitorCapture;
+
+mod cava;
+use cava::{Cava, CavaOptions};
+
+mod spectrum;
+use spectrum::SpectrumAnalyzer;
 
 static MAX_BAR_VALUE: u16 = ((1 << 16_u32) - 1) as u16;
 
-pub struct CavaOptions {
-    pub bars: usize,
+/// Same as the cava config
+const FRAMERATE: u32 = 45;
+
+/// Something that computes the heights of the visualizer bars, in a background thread
+pub trait VisualizerSource: Send {
+    /// Blocks until the next frame, and writes the height of each bar to `bars`
+    fn next_frame(&mut self, bars: &mut [u16]) -> ThreadResult;
+}
+
+/// Audio samples that are being played, that can be analyzed in-process instead of by cava
+pub trait SampleSource: Send {
+    /// Reads the samples played since the last call, mixed down to mono, without blocking.
+    /// Returns how many were read.
+    fn read(&mut self, buf: &mut [f32]) -> io::Result<usize>;
+    /// Zero if unknown
+    fn sample_rate(&self) -> u32;
 }
 
 type ThreadResult = StdResult<(), Box<dyn StdError + Send + Sync>>;
@@ -65,28 +63,14 @@ impl Default for ThreadHandle {
 }
 
 pub struct Visualizer {
-    tmp_path: PathBuf,
     data: Arc<Mutex<Vec<u16>>>,
     stop_flag: Arc<AtomicBool>,
     handle: ThreadHandle,
 }
 
 impl Visualizer {
-    pub fn new(opts: CavaOptions) -> Result<Self> {
-        let tmp_path = tori_tempfile(&opts)?;
-
-        let mut process = std::process::Command::new("cava")
-            .arg("-p")
-            .arg(&tmp_path)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .stdin(Stdio::null())
-            .spawn()
-            .map_err(|e| {
-                format!("Failed to spawn the visualizer process. Is `cava` installed? The received error was: {}", e)
-            })?;
-
-        let data = Arc::new(Mutex::new(vec![0_u16; opts.bars]));
+    pub fn new(mut source: Box<dyn VisualizerSource>, bars: usize) -> Self {
+        let data = Arc::new(Mutex::new(vec![0_u16; bars]));
         let stop_flag = Arc::new(AtomicBool::new(false));
         let handle: thread::JoinHandle<ThreadResult>;
 
@@ -94,34 +78,42 @@ impl Visualizer {
             let data = data.clone();
             let stop_flag = stop_flag.clone();
             handle = thread::spawn(move || {
-                let mut buf = vec![0_u8; 2 * opts.bars];
+                let mut bars = vec![0_u16; bars];
                 while !stop_flag.load(atomic::Ordering::Relaxed) {
-                    let stdout = process.stdout.as_mut().unwrap();
-                    let read_res = stdout.read_exact(&mut buf);
-
-                    if let Err(e) = read_res {
-                        let mut stderr_contents = String::new();
-                        let stderr = process.stderr.as_mut().unwrap();
-                        stderr.read_to_string(&mut stderr_contents).unwrap();
-                        return Err(format!("'{}'. Process stderr: {}", e, stderr_contents).into());
-                    }
-
-                    let mut data = data.lock().unwrap();
-                    for i in 0..data.len() {
-                        data[i] = u16::from_le_bytes([buf[2 * i], buf[2 * i + 1]]);
-                    }
+                    source.next_frame(&mut bars)?;
+                    data.lock().unwrap().copy_from_slice(&bars);
                 }
-                process.kill()?;
                 Ok(())
             });
         }
 
-        Ok(Self {
-            tmp_path,
+        Self {
             data,
             stop_flag,
             handle: ThreadHandle::Running(handle),
-        })
+        }
+    }
+
+    /// Creates the visualizer selected by `visualizer_backend` in the config. `player_samples` are
+    /// the samples given by the player, if it can give them.
+    pub fn from_config(player_samples: Option<Box<dyn SampleSource>>, bars: usize) -> Result<Self> {
+        let config = Config::global();
+        let noise_reduction = config.visualizer_noise_reduction;
+        let source: Box<dyn VisualizerSource> = match (config.visualizer_backend, player_samples) {
+            (VisualizerBackend::Auto | VisualizerBackend::Native, Some(samples)) => {
+                Box::new(SpectrumAnalyzer::new(samples, bars, noise_reduction))
+            }
+            (VisualizerBackend::Native, None) => Box::new(SpectrumAnalyzer::new(
+                Box::new(MonitorCapture::new()?),
+                bars,
+                noise_reduction,
+            )),
+            (VisualizerBackend::Auto | VisualizerBackend::Cava, _) => Box::new(Cava::new(CavaOptions {
+                bars,
+                noise_reduction,
+            })?),
+        };
+        Ok(Self::new(source, bars))
     }
 
     pub fn render(&self, buffer: &mut tui::buffer::Buffer) {
@@ -174,22 +166,5 @@ impl Drop for Visualizer {
     fn drop(&mut self) {
         // ~~hopefully~~ stop thread execution
         self.stop_flag.store(true, atomic::Ordering::Relaxed);
-        std::fs::remove_file(&self.tmp_path).ok();
-    }
-}
-
-fn tori_tempfile(opts: &CavaOptions) -> StdResult<PathBuf, std::io::Error> {
-    let path = std::env::temp_dir().join(format!("tori-{:x}", thread_rng().gen::<u32>()));
-
-    if path.is_file() {
-        return Err(io::ErrorKind::AlreadyExists.into());
     }
-
-    let cava_config = format!(cava_config!(), opts.bars);
-
-    let mut temp = File::create(&path)?;
-    temp.write_all(cava_config.as_bytes()).unwrap();
-    temp.flush().unwrap();
-
-    Ok(path)
 }
@@ -0,0 +1,285 @@
+//! FFT spectrum analyzer, used instead of cava when the player gives us the samples directly
+
+use std::{ops::Range, sync::Arc, thread, time::Duration};
+
+use realfft::{num_complex::Complex, RealFftPlanner, RealToComplex};
+
+use super::{SampleSource, ThreadResult, VisualizerSource, FRAMERATE, MAX_BAR_VALUE};
+
+/// Number of samples analyzed in each frame
+pub const FFT_LEN: usize = 4096;
+
+/// Same frequency range as the cava config
+const LOWER_CUTOFF_FREQ: f32 = 30.0;
+const HIGHER_CUTOFF_FREQ: f32 = 16000.0;
+
+/// Bars are empty at this volume, in dBFS, and full at 0 dBFS
+const DB_FLOOR: f32 = -60.0;
+
+/// How fast the bars fall, in bar heights per frame², like cava's gravity
+const GRAVITY: f32 = 0.004;
+
+/// Bars computed in-process from the samples of a [SampleSource]
+pub struct SpectrumAnalyzer {
+    source: Box<dyn SampleSource>,
+    /// Rebuilt if the sample rate changes
+    spectrum: Option<(Spectrum, u32)>,
+    /// The last [FFT_LEN] samples
+    history: Vec<f32>,
+    buf: Vec<f32>,
+    smoothing: Smoothing,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new(source: Box<dyn SampleSource>, bars: usize, noise_reduction: f32) -> Self {
+        Self {
+            source,
+            spectrum: None,
+            history: vec![0.0; FFT_LEN],
+            buf: vec![0.0; FFT_LEN],
+            smoothing: Smoothing::new(bars, noise_reduction),
+        }
+    }
+}
+
+impl VisualizerSource for SpectrumAnalyzer {
+    fn next_frame(&mut self, bars: &mut [u16]) -> ThreadResult {
+        thread::sleep(Duration::from_secs(1) / FRAMERATE);
+
+        let rate = self.source.sample_rate();
+        if rate == 0 {
+            return Ok(());
+        }
+
+        // Push the new samples, or silence if nothing is playing, so the bars drop
+        let mut read = self.source.read(&mut self.buf)?;
+        if read == 0 {
+            read = ((rate / FRAMERATE) as usize).min(FFT_LEN);
+            self.buf[..read].fill(0.0);
+        }
+        self.history.drain(..read);
+        self.history.extend_from_slice(&self.buf[..read]);
+
+        let spectrum = match &mut self.spectrum {
+            Some((spectrum, r)) if *r == rate => spectrum,
+            _ => &mut self.spectrum.insert((Spectrum::new(bars.len(), rate), rate)).0,
+        };
+        spectrum.compute(&self.history, bars);
+        self.smoothing.apply(bars);
+        Ok(())
+    }
+}
+
+/// Makes the bars move less erratically. Bars that go down fall with gravity instead of dropping
+/// instantly, and the noise reduction averages each bar with its previous values.
+struct Smoothing {
+    noise_reduction: f32,
+    /// Smoothed heights in [0, 1]
+    heights: Vec<f32>,
+    /// Height of each bar when it started falling
+    peaks: Vec<f32>,
+    /// For how many frames each bar has been falling
+    falling: Vec<u32>,
+}
+
+impl Smoothing {
+    fn new(bars: usize, noise_reduction: f32) -> Self {
+        Self {
+            noise_reduction: noise_reduction.clamp(0.0, 0.99),
+            heights: vec![0.0; bars],
+            peaks: vec![0.0; bars],
+            falling: vec![0; bars],
+        }
+    }
+
+    fn apply(&mut self, bars: &mut [u16]) {
+        for (i, bar) in bars.iter_mut().enumerate() {
+            let new = *bar as f32 / MAX_BAR_VALUE as f32;
+
+            // Gravity
+            let fallen = self.peaks[i] - GRAVITY * (self.falling[i] as f32).powi(2);
+            let target = if new >= fallen {
+                self.peaks[i] = new;
+                self.falling[i] = 0;
+                new
+            } else {
+                self.falling[i] += 1;
+                fallen
+            };
+
+            // Noise reduction
+            let nr = self.noise_reduction;
+            self.heights[i] = self.heights[i] * nr + target * (1.0 - nr);
+            *bar = (self.heights[i].clamp(0.0, 1.0) * MAX_BAR_VALUE as f32) as u16;
+        }
+    }
+}
+
+pub struct Spectrum {
+    fft: Arc<dyn RealToComplex<f32>>,
+    window: Vec<f32>,
+    input: Vec<f32>,
+    output: Vec<Complex<f32>>,
+    /// Range of FFT bins that each bar covers
+    bins: Vec<Range<usize>>,
+    /// Converts the magnitude of a bin to the amplitude of a sine wave in [0, 1]
+    scale: f32,
+}
+
+impl Spectrum {
+    pub fn new(bars: usize, sample_rate: u32) -> Self {
+        let fft = RealFftPlanner::<f32>::new().plan_fft_forward(FFT_LEN);
+
+        // Hann window
+        let window: Vec<f32> = (0..FFT_LEN)
+            .map(|i| {
+                let x = std::f32::consts::PI * i as f32 / FFT_LEN as f32;
+                x.sin().powi(2)
+            })
+            .collect();
+        let scale = 2.0 / window.iter().sum::<f32>();
+
+        Self {
+            input: fft.make_input_vec(),
+            output: fft.make_output_vec(),
+            bins: bar_bins(bars, sample_rate),
+            fft,
+            window,
+            scale,
+        }
+    }
+
+    /// Computes the height of each bar from the last [FFT_LEN] samples
+    pub fn compute(&mut self, samples: &[f32], bars: &mut [u16]) {
+        let samples = &samples[samples.len().saturating_sub(FFT_LEN)..];
+        let padding = FFT_LEN - samples.len();
+        self.input[..padding].fill(0.0);
+        for ((x, s), w) in self.input[padding..]
+            .iter_mut()
+            .zip(samples)
+            .zip(&self.window[padding..])
+        {
+            *x = s * w;
+        }
+
+        if self.fft.process(&mut self.input, &mut self.output).is_err() {
+            return;
+        }
+
+        for (bar, bins) in bars.iter_mut().zip(&self.bins) {
+            let magnitude = self.output[bins.clone()]
+                .iter()
+                .map(|c| c.norm())
+                .fold(0.0, f32::max);
+            let db = 20.0 * (magnitude * self.scale).max(1e-9).log10();
+            let height = ((db - DB_FLOOR) / -DB_FLOOR).clamp(0.0, 1.0);
+            *bar = (height * MAX_BAR_VALUE as f32) as u16;
+        }
+    }
+}
+
+/// Splits the frequency range into `bars` logarithmically spaced bands, and returns the FFT bins
+/// of each one. Every band has at least one bin.
+fn bar_bins(bars: usize, sample_rate: u32) -> Vec<Range<usize>> {
+    let nyquist = sample_rate as f32 / 2.0;
+    let high = HIGHER_CUTOFF_FREQ.min(nyquist);
+    let bin_of = |freq: f32| (freq * FFT_LEN as f32 / sample_rate as f32) as usize;
+    let last_bin = FFT_LEN / 2;
+
+    let freq_at =
+        |i: usize| LOWER_CUTOFF_FREQ * (high / LOWER_CUTOFF_FREQ).powf(i as f32 / bars as f32);
+
+    (0..bars)
+        .map(|i| {
+            let start = bin_of(freq_at(i)).min(last_bin);
+            let end = bin_of(freq_at(i + 1)).clamp(start + 1, last_bin + 1);
+            start..end
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sine_peak() {
+        let rate = 48000;
+        let freq = 1000.0;
+        let samples: Vec<f32> = (0..FFT_LEN)
+            .map(|i| (2.0 * std::f32::consts::PI * freq * i as f32 / rate as f32).sin())
+            .collect();
+
+        let mut spectrum = Spectrum::new(32, rate);
+        let mut bars = vec![0; 32];
+        spectrum.compute(&samples, &mut bars);
+
+        let peak = (0..bars.len()).max_by_key(|&i| bars[i]).unwrap();
+        let bin = (freq * FFT_LEN as f32 / rate as f32) as usize;
+        assert!(spectrum.bins[peak].contains(&bin));
+        assert!(bars[peak] > MAX_BAR_VALUE / 10 * 9);
+    }
+
+    #[test]
+    fn test_smoothing_falls_gradually() {
+        let mut smoothing = Smoothing::new(1, 0.0);
+        let mut bars = [MAX_BAR_VALUE];
+        smoothing.apply(&mut bars);
+        assert_eq!(bars[0], MAX_BAR_VALUE);
+
+        // The bar doesn't drop to zero right away, but keeps falling faster
+        let mut last = MAX_BAR_VALUE;
+        let mut last_drop = 0;
+        for _ in 0..5 {
+            bars[0] = 0;
+            smoothing.apply(&mut bars);
+            assert!(bars[0] > 0 && bars[0] <= last);
+            assert!(last - bars[0] >= last_drop);
+            last_drop = last - bars[0];
+            last = bars[0];
+        }
+
+        // and rises instantly
+        bars[0] = MAX_BAR_VALUE;
+        smoothing.apply(&mut bars);
+        assert_eq!(bars[0], MAX_BAR_VALUE);
+    }
+
+    #[test]
+    fn test_noise_reduction() {
+        let mut smoothing = Smoothing::new(1, 0.5);
+        let mut bars = [MAX_BAR_VALUE];
+        smoothing.apply(&mut bars);
+        assert_eq!(bars[0], MAX_BAR_VALUE / 2);
+    }
+
+    /// Never has any samples
+    struct Silent(u32);
+
+    impl SampleSource for Silent {
+        fn read(&mut self, _buf: &mut [f32]) -> std::io::Result<usize> {
+            Ok(0)
+        }
+
+        fn sample_rate(&self) -> u32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_silence_at_high_sample_rate() {
+        let mut analyzer = SpectrumAnalyzer::new(Box::new(Silent(192000)), 16, 0.0);
+        let mut bars = vec![0; 16];
+        analyzer.next_frame(&mut bars).unwrap();
+        assert_eq!(analyzer.history.len(), FFT_LEN);
+        assert!(bars.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_silence() {
+        let mut spectrum = Spectrum::new(16, 44100);
+        let mut bars = vec![1; 16];
+        spectrum.compute(&[0.0; 100], &mut bars);
+        assert!(bars.iter().all(|&b| b == 0));
+    }
+}